@@ -1,30 +1,189 @@
-mod git_object;
-mod object;
-mod repository;
+use std::{
+    env,
+    fmt::Display,
+    fs::{canonicalize, read, read_to_string, write},
+    io::{self, Read, Write},
+    panic,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-use std::{env, fs::read_to_string, path::PathBuf};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use clap_complete::{generate, Shell};
+use clap_mangen::Man;
+use gotlib::object::{
+    blob::Blob,
+    oid::Oid,
+    serialise::Serialise,
+    tag::Tag,
+    tree::{FileMode, Tree, TreeEntry},
+    tree_builder::TreeBuilder,
+    validate_object,
+};
 
-use clap::{Parser, Subcommand};
-use object::{blob::Blob, serialise::Serialise};
-
-use git_object::GitObject;
-use repository::Repository;
+use gotlib::checkout::{checkout_paths, switch, SwitchOptions};
+use gotlib::date::Date;
+use gotlib::for_each_ref::{format_ref_info, list_ref_infos, sort_ref_infos, DEFAULT_FORMAT};
+use gotlib::hooks::{run_event_hook, Event, NULL_SHA};
+use gotlib::ident::{editor, signature, Role};
+use gotlib::merge_file::{merge_file, MergeFavor, MergeFileOptions};
+use gotlib::name_rev::name_revs;
+use gotlib::pathspec::Pathspec;
+use gotlib::platform::to_tree_path;
+use gotlib::repository::{InitOptions, Repository};
+use gotlib::server_info::update_server_info;
+use gotlib::tag::{annotation, list_tags, short_name, verify_tag};
+use gotlib::validate::validate_tree_path;
 
 #[derive(Parser)]
+#[command(name = "got", disable_help_subcommand = true)]
 struct Cli {
     #[clap(subcommand)]
     command: Option<Commands>,
+
+    /// Do not use replacement refs to replace objects
+    #[arg(long, global = true, default_value_t = false)]
+    no_replace_objects: bool,
+
+    /// Only print results and errors
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "verbose"
+    )]
+    quiet: bool,
+
+    /// Print more details about what is being done
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+// Informational messages of commands, which -q silences and -v extends.
+// Results (object ids, listings) are printed regardless, as scripts read them.
+struct Output {
+    verbosity: Verbosity,
+}
+
+impl Output {
+    fn info(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("{}", message);
+        }
+    }
+
+    fn detail(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Verbose {
+            println!("{}", message);
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Add file contents to the index (not implemented yet)
     Add,
+    /// Print the content of an object
+    #[command(after_long_help = "Examples:
+  got cat-file commit 8171e35338318dcc12d4b3e9801281cdec6365ba
+      Print a commit, failing if the object is not a commit.
+  got cat-file -e 8171e35338318dcc12d4b3e9801281cdec6365ba && echo exists
+      Check whether an object exists without printing anything.")]
     CatFile {
-        object_type: String,
-        object: String,
+        /// Exit with zero status if the object exists and is valid
+        #[arg(short, default_value_t = false)]
+        exists: bool,
+
+        /// Expected type of the object: blob, tree, commit or tag
+        object_type: Option<String>,
+        object: Option<String>,
     },
-    Checkout,
+    /// Switch branches or restore worktree files
+    #[command(after_long_help = "Examples:
+  got checkout topic
+      Switch to branch topic, refusing if that would overwrite local changes.
+  got checkout -m topic
+      Switch to branch topic, merging local changes into the files it changes.
+  got checkout HEAD -- src/main.rs
+      Give back src/main.rs as it was in the last commit.
+  got checkout --no-overlay v1.0 -- docs
+      Make the docs directory exactly as in tag v1.0, deleting newer files.")]
+    Checkout {
+        /// Branch to switch to, or commit, tag or tree to restore files from
+        tree_ish: String,
+
+        /// Discard local changes when switching branches
+        #[arg(short, long, default_value_t = false, conflicts_with = "merge")]
+        force: bool,
+
+        /// Merge local changes into the branch being switched to
+        #[arg(short, long, default_value_t = false)]
+        merge: bool,
+
+        /// When restoring paths, delete the matching files that are not in the tree
+        #[arg(long, default_value_t = false)]
+        no_overlay: bool,
+
+        /// Paths to restore, as a pathspec, instead of switching branches
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+    /// Write the worktree files as a tree and print its id
+    #[command(after_long_help = "Examples:
+  got commit -v
+      Write every file of the worktree and print the resulting tree id,
+      listing each file as it is added.")]
     Commit,
+    /// Print a shell completion script
+    #[command(after_long_help = "Examples:
+  got completions bash > ~/.local/share/bash-completion/completions/got
+  got completions zsh > ~/.zfunc/_got")]
+    Completions { shell: Shell },
+    /// List refs with their information
+    #[command(after_long_help = "Examples:
+  got for-each-ref --format='%(refname:short) %(committerdate:relative)' refs/heads
+      List branches with how long ago they were last committed to.
+  got for-each-ref --sort=-committerdate --count=5
+      Show the five most recently updated refs.
+  got for-each-ref 'refs/tags/v1.*' ':!refs/tags/v1.0'
+      List v1 tags except v1.0, using pathspec patterns.")]
+    ForEachRef {
+        /// Format of each line, with placeholders like %(refname) or %(committerdate:iso)
+        #[arg(long, default_value_t = String::from(DEFAULT_FORMAT))]
+        format: String,
+
+        /// Key to sort by, prefixed with '-' for descending order. Can be repeated.
+        #[arg(long, action = ArgAction::Append)]
+        sort: Vec<String>,
+
+        /// Stop after showing this many refs
+        #[arg(long)]
+        count: Option<usize>,
+
+        patterns: Vec<String>,
+    },
+    /// Clean up the object store
+    #[command(after_long_help = "Examples:
+  got gc
+      Remove temporary files left by object writes interrupted over two weeks
+      ago (gc.pruneExpire, if set).
+  got gc --prune=now
+      Remove them all, when no other got process is writing objects.")]
+    Gc {
+        /// Remove temporary files older than this date (like now or 1.day.ago), or never
+        #[arg(long, value_name = "DATE")]
+        prune: Option<String>,
+    },
+    /// Compute the id of a file, optionally writing it (not implemented yet)
     HashObject {
         #[arg(short, long)]
         r#type: Option<String>,
@@ -34,68 +193,914 @@ enum Commands {
 
         filepath: String,
     },
+    /// Show help for got or one of its commands
+    #[command(after_long_help = "Examples:
+  got help --all
+      List every command, grouped by kind.
+  got help --man name-rev | man -l -
+      Read the help of name-rev as a man page.")]
+    Help {
+        /// Print a man page in roff format instead
+        #[arg(long, default_value_t = false)]
+        man: bool,
+
+        /// List all commands, grouped into porcelain and plumbing
+        #[arg(short, long, default_value_t = false, conflicts_with = "command")]
+        all: bool,
+
+        command: Option<String>,
+    },
+    /// Create an empty repository
+    #[command(after_long_help = "Examples:
+  got init -b main project
+      Create a repository in ./project whose first branch is main.
+  got init --template=~/got-template project
+      Copy hooks, info/exclude and a description from a template.")]
     Init {
+        /// Directory whose contents are copied into the new .got directory.
+        /// Empty to use no template.
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Name of the branch HEAD points to
+        #[arg(short = 'b', long)]
+        initial_branch: Option<String>,
+
         path: String,
     },
+    /// Show commit logs (not implemented yet)
     Log,
+    /// List the contents of a tree (not implemented yet)
     LsTree,
+    /// Join two histories together (not implemented yet)
     Merge,
+    /// Run a three-way merge of three files
+    #[command(after_long_help = "Examples:
+  got merge-file -p current.txt base.txt other.txt > merged.txt
+      Merge without touching current.txt. The exit code is the number
+      of conflicts.
+  got merge-file --union -L ours -L base -L theirs a.txt base.txt b.txt
+      Keep both sides of every conflict, labelling the markers.")]
+    MergeFile {
+        /// Labels for the current, base and other files in conflict markers
+        #[arg(short = 'L', action = ArgAction::Append)]
+        labels: Vec<String>,
+
+        /// Resolve conflicts favouring the current file
+        #[arg(long, default_value_t = false, conflicts_with_all = ["theirs", "union"])]
+        ours: bool,
+
+        /// Resolve conflicts favouring the other file
+        #[arg(long, default_value_t = false, conflicts_with = "union")]
+        theirs: bool,
+
+        /// Resolve conflicts keeping the lines from both files
+        #[arg(long, default_value_t = false)]
+        union: bool,
+
+        /// Print the result instead of overwriting the current file
+        #[arg(short = 'p', long, default_value_t = false)]
+        stdout: bool,
+
+        /// Length of the conflict markers
+        #[arg(long, default_value_t = 7)]
+        marker_size: usize,
+
+        current: String,
+        base: String,
+        other: String,
+    },
+    /// Create a tag object read from stdin, checking it first
+    #[command(after_long_help = "Examples:
+  got mktag < tag.txt
+      Validate the tag in tag.txt, write it and print its id.")]
+    Mktag,
+    /// Create a tree object from ls-tree formatted lines read from stdin
+    #[command(after_long_help = "Examples:
+  printf '100644 blob %s\\tREADME\\n' $blob | got mktree
+      Write a tree with a single README file and print its id.")]
+    Mktree {
+        /// Input lines are terminated by NUL instead of line feed
+        #[arg(short = 'z', default_value_t = false)]
+        nul_terminated: bool,
+
+        /// Allow entries pointing to objects missing from the repository
+        #[arg(long, default_value_t = false)]
+        missing: bool,
+    },
+    /// Name commits relative to refs, like master~2
+    #[command(after_long_help = "Examples:
+  got name-rev 0ae2f426b50cd7a90315653262caecd42ec630b3
+      Print the commit with a name like topic~4.
+  got name-rev --tags --name-only 0ae2f426b50cd7a90315653262caecd42ec630b3
+      Print only a name based on tags, like v1~2.")]
+    NameRev {
+        /// Only use tags to name commits
+        #[arg(long, default_value_t = false)]
+        tags: bool,
+
+        /// Print only the name, not the object id
+        #[arg(long, default_value_t = false)]
+        name_only: bool,
+
+        commits: Vec<String>,
+    },
+    /// Reapply commits on top of another base (not implemented yet)
     Rebase,
+    /// Create, list and delete refs that replace objects
+    #[command(after_long_help = "Examples:
+  got replace 8171e35338318dcc12d4b3e9801281cdec6365ba 0ae2f426b50cd7a90315653262caecd42ec630b3
+      Read the second object wherever the first one is asked for.
+  got replace -d 8171e35338318dcc12d4b3e9801281cdec6365ba
+      Stop replacing it.")]
+    Replace {
+        /// Delete the replace refs of the given objects
+        #[arg(short, long, default_value_t = false)]
+        delete: bool,
+
+        /// Overwrite an existing replace ref
+        #[arg(short, long, default_value_t = false)]
+        force: bool,
+
+        /// List replaced objects
+        #[arg(short, long, default_value_t = false)]
+        list: bool,
+
+        objects: Vec<String>,
+    },
+    /// Pick out and massage parameters (not implemented yet)
     RevParse,
+    /// Remove files from the worktree and the index (not implemented yet)
     Rm,
+    /// List refs (not implemented yet)
     ShowRef,
-    Tag,
+    /// List or verify tags
+    #[command(after_long_help = "Examples:
+  got tag -l 'v1.*' --sort=-version:refname
+      List v1 tags, newest version first (v1.10 before v1.9).
+  got tag --contains 8171e35338318dcc12d4b3e9801281cdec6365ba -n
+      List the tags containing a commit, with the first line of their messages.
+  got tag -v v1.0
+      Check the GPG signature of tag v1.0.
+
+Creating and deleting tags is not implemented yet.")]
+    Tag {
+        /// List tags, optionally only those matching the given patterns
+        #[arg(short, long, default_value_t = false)]
+        list: bool,
+
+        /// Only list tags containing this commit
+        #[arg(long, value_name = "COMMIT")]
+        contains: Option<String>,
+
+        /// Key to sort by (like refname or version:refname), prefixed with '-' for descending order. Can be repeated.
+        #[arg(long, action = ArgAction::Append)]
+        sort: Vec<String>,
+
+        /// Print the first <N> lines of the message of each tag (1 if not given)
+        #[arg(short = 'n', value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        lines: Option<usize>,
+
+        /// Verify the GPG signatures of the given tags
+        #[arg(short = 'v', long, default_value_t = false, conflicts_with_all = ["list", "contains", "sort", "lines"])]
+        verify: bool,
+
+        // Takes the place of the global --verbose, so -v can mean --verify as
+        // in git. Its value still reaches Cli::verbose.
+        /// Print more details about what is being done
+        #[arg(long, action = ArgAction::Count)]
+        verbose: u8,
+
+        names: Vec<String>,
+    },
+    /// Update the files that let dumb servers serve the repository
+    UpdateServerInfo,
+    /// Print a got logical variable
+    #[command(after_long_help = "Variables:
+  GOT_AUTHOR_IDENT, GOT_COMMITTER_IDENT, GOT_DEFAULT_BRANCH, GOT_EDITOR
+
+Examples:
+  got var GOT_COMMITTER_IDENT
+      Print who would be recorded as committer, and when.")]
+    Var { variable: String },
+}
+
+// Exit codes and messages follow git's conventions, which scripts rely on: 0
+// for success, 1 for errors that still let the command finish, 128 for fatal
+// errors and 129 for wrong usage. Messages go to stderr prefixed with "fatal:"
+// or "error:".
+const EXIT_FATAL: i32 = 128;
+const EXIT_USAGE: i32 = 129;
+
+fn die(message: impl Display) -> ! {
+    eprintln!("fatal: {}", message);
+    process::exit(EXIT_FATAL)
+}
+
+fn error(message: impl Display) {
+    eprintln!("error: {}", message);
+}
+
+fn usage(usage: &str) -> ! {
+    eprintln!("usage: {}", usage);
+    process::exit(EXIT_USAGE)
+}
+
+fn find_repository() -> Repository {
+    let current_dir = env::current_dir()
+        .unwrap_or_else(|error| die(format!("Unable to read current directory: {}", error)));
+
+    Repository::discover(current_dir).unwrap_or_else(|error| die(error))
+}
+
+// Like find_repository, for commands that also work outside of a repository.
+fn find_optional_repository() -> Option<Repository> {
+    let current_dir = env::current_dir()
+        .unwrap_or_else(|error| die(format!("Unable to read current directory: {}", error)));
+
+    Repository::repo_find(current_dir).unwrap_or_else(|error| die(error))
+}
+
+fn cat_file_command(object_type: Option<&str>, exists: bool, object: &str) {
+    let repo = find_repository();
+
+    // With -e, only the exit code tells whether the object exists and can be
+    // read, so scripts can test for it quietly.
+    if exists {
+        match repo.object_read_raw(object) {
+            Ok(_) => process::exit(0),
+            Err(_) if !repo.object_exists(object) => process::exit(1),
+            Err(message) => {
+                error(message);
+                process::exit(1)
+            }
+        }
+    }
+
+    let object_type = object_type.unwrap_or_else(|| usage("got cat-file (<type> | -e) <object>"));
+
+    let (actual_type, content) = repo
+        .object_read_raw(object)
+        .unwrap_or_else(|error| die(format!("Not a valid object name {}: {}", object, error)));
+
+    if actual_type != object_type {
+        die(format!(
+            "{} is a {}, not a {}",
+            object, actual_type, object_type
+        ));
+    }
+
+    io::stdout()
+        .write_all(&content)
+        .unwrap_or_else(|error| die(format!("Could not write object: {}", error)));
+}
+
+fn checkout_command(output: &Output, tree_ish: &str, overlay: bool, paths: &[String]) {
+    let repo = find_repository();
+
+    let pathspec = Pathspec::parse(paths).unwrap_or_else(|error| die(error));
+    let restored =
+        checkout_paths(&repo, tree_ish, &pathspec, overlay).unwrap_or_else(|error| die(error));
+
+    if restored.is_empty() {
+        error(format!(
+            "pathspec {} did not match any file(s) known to got",
+            paths
+                .iter()
+                .map(|path| format!("'{}'", path))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+        process::exit(1);
+    }
+
+    for path in &restored {
+        output.detail(format!("restore '{}'", path));
+    }
+
+    output.info(format!(
+        "Updated {} path{} from {}",
+        restored.len(),
+        if restored.len() == 1 { "" } else { "s" },
+        tree_ish
+    ));
+
+    let head = repo.ref_read("HEAD").unwrap_or_else(|| NULL_SHA.to_owned());
+
+    run_checkout_hook(
+        &repo,
+        Event::Checkout {
+            old: head.clone(),
+            new: head,
+            branch_switch: false,
+        },
+    );
+}
+
+// Runs post-checkout once the checkout is done and reported. A failing hook
+// cannot undo it, but still fails the command, as in git.
+fn run_checkout_hook(repo: &Repository, event: Event) {
+    if let Err(message) = run_event_hook(repo, &event) {
+        error(message);
+        process::exit(1);
+    }
+}
+
+fn switch_command(output: &Output, target: &str, options: &SwitchOptions) {
+    let repo = find_repository();
+
+    let previous_branch = repo.head_branch();
+    let previous_commit = repo.ref_read("HEAD");
+
+    let result = switch(&repo, target, options).unwrap_or_else(|message| {
+        error(message);
+        process::exit(1)
+    });
+
+    for path in &result.conflicts {
+        error(format!("Conflicts while merging local changes to {}", path));
+    }
+
+    match &result.branch {
+        Some(branch) if previous_branch.as_ref() == Some(branch) => output.info(format!(
+            "Already on '{}'",
+            branch.trim_start_matches("refs/heads/")
+        )),
+        Some(branch) => output.info(format!(
+            "Switched to branch '{}'",
+            branch.trim_start_matches("refs/heads/")
+        )),
+        None if previous_branch.is_none() && previous_commit.as_ref() == Some(&result.commit) => {
+            output.info(format!("HEAD is already at {}", result.commit))
+        }
+        None => output.info(format!("HEAD is now at {}", result.commit)),
+    }
+
+    run_checkout_hook(
+        &repo,
+        Event::Checkout {
+            old: previous_commit.unwrap_or_else(|| NULL_SHA.to_owned()),
+            new: result.commit,
+            branch_switch: true,
+        },
+    );
+}
+
+// Completion scripts are generated for the name got was run as, so they
+// attach to whatever name the binary was installed under. Branch and tag
+// names are not part of the scripts; `got for-each-ref
+// --format='%(refname:short)'` lists them for shells that want them.
+fn completions_command(shell: Shell) {
+    let bin_name = env::args_os()
+        .next()
+        .as_deref()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("got"));
+
+    generate(shell, &mut Cli::command(), bin_name, &mut io::stdout());
+}
+
+// Commands as listed by `got help --all`, grouped like git does.
+const COMMAND_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "Main porcelain commands",
+        &[
+            "add", "checkout", "commit", "gc", "init", "log", "merge", "rebase", "rm", "tag",
+        ],
+    ),
+    (
+        "Ancillary commands",
+        &[
+            "completions",
+            "help",
+            "merge-file",
+            "name-rev",
+            "replace",
+            "update-server-info",
+        ],
+    ),
+    (
+        "Low-level commands (plumbing)",
+        &[
+            "cat-file",
+            "for-each-ref",
+            "hash-object",
+            "ls-tree",
+            "mktag",
+            "mktree",
+            "rev-parse",
+            "show-ref",
+            "var",
+        ],
+    ),
+];
+
+fn help_all() {
+    let cli = Cli::command();
+
+    for (index, (group, names)) in COMMAND_GROUPS.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+
+        println!("{}", group);
+
+        for name in names.iter() {
+            let about = cli
+                .find_subcommand(name)
+                .and_then(|command| command.get_about())
+                .map(|about| about.to_string())
+                .unwrap_or_default();
+
+            println!("   {:<20} {}", name, about);
+        }
+    }
+}
+
+fn help_command(man: bool, all: bool, command_name: Option<&str>) {
+    if all {
+        help_all();
+        return;
+    }
+
+    let mut cli = Cli::command();
+    cli.build();
+
+    let (mut command, title) = match command_name {
+        Some(name) => match cli.find_subcommand(name) {
+            Some(command) => (command.clone(), format!("got-{}", name)),
+            None => die(format!("'{}' is not a got command", name)),
+        },
+        None => (cli, String::from("got")),
+    };
+
+    let result = if man {
+        Man::new(command).title(title).render(&mut io::stdout())
+    } else {
+        command.print_long_help()
+    };
+
+    result.unwrap_or_else(|error| die(format!("Could not print help: {}", error)));
 }
 
-fn commit_command() {
-    let repo = Repository::repo_find(env::current_dir().unwrap())
-        .expect("Could not find a valid Got repository in this location.");
+fn commit_command(output: &Output) {
+    let repo = find_repository();
 
     let files = repo
         .list_files()
-        .expect("Could not read files in repository.");
+        .unwrap_or_else(|error| die(format!("Could not read files in repository: {}", error)));
 
-    println!("{:?}", files);
+    let mut tree_builder = TreeBuilder::new();
 
-    let blobs = files
-        .iter()
-        .map(|path| read_to_string(path).unwrap())
-        .map(|data| Blob::new(data));
+    for path in files.iter() {
+        let content = read(path).unwrap_or_else(|error| {
+            die(format!("Could not read file {}: {}", path.display(), error))
+        });
+        let blob_oid = Oid::from_hex(&repo.object_write(&Blob::new(content), true))
+            .unwrap_or_else(|error| die(error));
 
-    for blob in blobs {
-        repo.object_write(&blob, true);
+        let relative_path = path.strip_prefix(repo.worktree()).unwrap_or_else(|_| {
+            die(format!(
+                "File {} is outside of the worktree",
+                path.display()
+            ))
+        });
+        let tree_path = to_tree_path(relative_path);
+        output.detail(format!("add '{}'", tree_path));
+
+        tree_builder
+            .insert(&tree_path, FileMode::Blob, blob_oid)
+            .unwrap_or_else(|error| die(format!("Could not add file to tree: {}", error)));
+    }
+
+    println!("{}", tree_builder.write(&repo));
+}
+
+fn for_each_ref_command(format: &str, sort: &[String], count: Option<usize>, patterns: &[String]) {
+    let repo = find_repository();
+
+    let patterns = Pathspec::parse(patterns).unwrap_or_else(|error| die(error));
+    let mut refs = list_ref_infos(&repo, &patterns).unwrap_or_else(|error| die(error));
+
+    sort_ref_infos(&mut refs, sort).unwrap_or_else(|error| die(error));
+
+    let mut stdout = io::stdout().lock();
+
+    for info in refs.iter().take(count.unwrap_or(usize::MAX)) {
+        let mut line = format_ref_info(info, format).unwrap_or_else(|error| die(error));
+        line.push(b'\n');
+
+        stdout
+            .write_all(&line)
+            .unwrap_or_else(|error| die(format!("Could not write output: {}", error)));
+    }
+}
+
+// Temporary object files younger than this may belong to a write in
+// progress, so they are kept unless --prune or gc.pruneExpire say otherwise.
+const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+fn gc_command(output: &Output, prune: Option<&str>) {
+    let repo = find_repository();
+
+    let expire = prune
+        .map(str::to_owned)
+        .or_else(|| repo.config_get("gc", "pruneexpire"))
+        .unwrap_or_else(|| DEFAULT_PRUNE_EXPIRE.to_owned());
+
+    if expire == "never" {
+        return;
+    }
+
+    let date = Date::parse(&expire)
+        .unwrap_or_else(|| die(format!("Invalid prune expiry date: {:?}", expire)));
+    let age = Date::now().timestamp.saturating_sub(date.timestamp).max(0) as u64;
+
+    let removed = repo
+        .object_sweep_temporaries(Duration::from_secs(age))
+        .unwrap_or_else(|error| die(format!("Could not clean up objects: {}", error)));
+
+    output.detail(format!("Removed {} temporary object files", removed));
+}
+
+// Exits with the number of conflicts (capped at 127), like git merge-file.
+fn merge_file_command(
+    labels: &[String],
+    favor: MergeFavor,
+    stdout: bool,
+    marker_size: usize,
+    paths: [&String; 3],
+) {
+    if labels.len() > 3 {
+        die("Too many labels given, at most 3 (current, base and other) are allowed");
+    }
+
+    let [current, base, other] = paths.map(|path| {
+        read_to_string(path).unwrap_or_else(|error| die(format!("Could not read file: {}", error)))
+    });
+
+    let options = MergeFileOptions {
+        favor,
+        marker_size,
+        ours_label: labels.first().unwrap_or(paths[0]).to_owned(),
+        theirs_label: labels.get(2).unwrap_or(paths[2]).to_owned(),
+    };
+
+    let result = merge_file(&current, &base, &other, &options);
+
+    if stdout {
+        print!("{}", result.content);
+    } else {
+        write(paths[0], result.content)
+            .unwrap_or_else(|error| die(format!("Could not write merged file: {}", error)));
+    }
+
+    process::exit(result.conflicts.min(127) as i32);
+}
+
+// Reads a tag object from stdin, validates it and writes it to the repository.
+fn mktag_command() {
+    let repo = find_repository();
+
+    let mut content = String::new();
+    io::stdin()
+        .read_to_string(&mut content)
+        .unwrap_or_else(|error| die(format!("Could not read tag from stdin: {}", error)));
+
+    let (object, object_type) = match Tag::validate(&content) {
+        Ok(tagged) => tagged,
+        Err(error) => die(format!("Invalid tag: {}", error)),
+    };
+
+    match repo.object_read_raw(&object.to_hex()) {
+        Ok((actual_type, _)) if actual_type == object_type => {}
+        Ok((actual_type, _)) => die(format!(
+            "Tagged object {} is a {}, not a {}",
+            object, actual_type, object_type
+        )),
+        Err(error) => die(format!(
+            "Could not read tagged object {}: {}",
+            object, error
+        )),
+    }
+
+    println!("{}", repo.object_write(&Tag::new(content), true));
+}
+
+// Reads ls-tree formatted lines (`<mode> <type> <sha>\t<name>`) from stdin and
+// writes the tree they describe.
+fn mktree_command(nul_terminated: bool, missing: bool) {
+    let repo = find_repository();
+
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .unwrap_or_else(|error| die(format!("Could not read tree entries from stdin: {}", error)));
+
+    let terminator = if nul_terminated { '\x00' } else { '\n' };
+    let mut entries = vec![];
+
+    for line in input.split(terminator).filter(|line| !line.is_empty()) {
+        let (info, name) = line
+            .split_once('\t')
+            .unwrap_or_else(|| die(format!("Input is not in ls-tree format: {:?}", line)));
+
+        let (mode, object_type, sha) = match info.split(' ').collect::<Vec<_>>()[..] {
+            [mode, object_type, sha] => (mode, object_type, sha),
+            _ => die(format!("Input is not in ls-tree format: {:?}", line)),
+        };
+
+        let mode = FileMode::from_mode_str(mode).unwrap_or_else(|error| die(error));
+        let oid = Oid::from_hex(sha).unwrap_or_else(|error| die(format!("{}: {:?}", error, sha)));
+
+        if mode.object_type() != object_type {
+            die(format!(
+                "Entry {:?} is a {} but its mode says {}",
+                name,
+                object_type,
+                mode.object_type()
+            ));
+        }
+
+        if name.contains('/') {
+            die(format!("Path {:?} contains a slash", name));
+        }
+
+        validate_tree_path(name).unwrap_or_else(|error| die(error));
+
+        // Submodule commits live in another repository, so they are never checked.
+        if mode != FileMode::Commit {
+            match repo.object_read_raw(sha) {
+                Ok((actual_type, _)) if actual_type != object_type => die(format!(
+                    "Entry {:?} is a {}, not a {}",
+                    name, actual_type, object_type
+                )),
+                Err(_) if !missing => {
+                    die(format!("Entry {:?} points to missing object {}", name, sha))
+                }
+                _ => {}
+            }
+        }
+
+        entries.push(TreeEntry {
+            mode,
+            name: name.to_owned(),
+            oid,
+        });
+    }
+
+    // Entries are sorted when the tree is built, but names given twice are
+    // only caught by checking the result.
+    let tree = Tree::new(entries);
+    validate_object("tree", &tree.serialise())
+        .unwrap_or_else(|error| die(format!("Invalid tree: {}", error)));
+
+    println!("{}", repo.object_write(&tree, true));
+}
+
+fn name_rev_command(tags: bool, name_only: bool, commits: &[String]) {
+    let repo = find_repository();
+
+    let names = name_revs(&repo, tags);
+
+    for commit in commits {
+        // Accept refs like HEAD as well as object ids.
+        let sha = match Oid::from_hex(commit) {
+            Ok(oid) => oid.to_hex(),
+            Err(_) => match repo.ref_read(commit) {
+                Some(sha) => sha,
+                None => {
+                    error(format!("Could not get sha1 for {}. Skipping.", commit));
+                    continue;
+                }
+            },
+        };
+
+        let name = match names.get(&sha) {
+            // As in git, names are only abbreviated when asking for tags and names only.
+            Some(name) if tags && name_only => name.trim_start_matches("tags/"),
+            Some(name) => name.as_str(),
+            None => "undefined",
+        };
+
+        if name_only {
+            println!("{}", name);
+        } else {
+            println!("{} {}", commit, name);
+        }
     }
 }
 
+fn replace_command(output: &Output, delete: bool, force: bool, list: bool, objects: &[String]) {
+    let repo = find_repository();
+
+    if list || (objects.is_empty() && !delete) {
+        for name in repo.ref_list("refs/replace") {
+            println!("{}", name.trim_start_matches("refs/replace/"));
+        }
+    } else if delete {
+        for object in objects {
+            repo.ref_delete(&format!("refs/replace/{}", object))
+                .unwrap_or_else(|error| die(format!("Could not delete replace ref: {}", error)));
+            output.info(format!("Deleted replace ref '{}'", object));
+        }
+    } else {
+        let (object, replacement) = match objects {
+            [object, replacement] => (object, replacement),
+            _ => usage("got replace [-f] <object> <replacement>"),
+        };
+
+        if object == replacement {
+            die(format!(
+                "New object is the same as the old one: '{}'",
+                object
+            ));
+        }
+
+        if !repo.object_exists(object) || !repo.object_exists(replacement) {
+            die("Both objects must exist in the repository");
+        }
+
+        let name = format!("refs/replace/{}", object);
+
+        if !force && repo.ref_read(&name).is_some() {
+            die(format!("Replace ref '{}' already exists", object));
+        }
+
+        repo.ref_write(&name, replacement)
+            .unwrap_or_else(|error| die(format!("Could not write replace ref: {}", error)));
+    }
+}
+
+fn tag_list_command(
+    patterns: &[String],
+    contains: Option<&str>,
+    sort: &[String],
+    lines: Option<usize>,
+) {
+    let repo = find_repository();
+
+    let patterns = Pathspec::parse(patterns).unwrap_or_else(|error| die(error));
+    let mut tags = list_tags(&repo, &patterns, contains).unwrap_or_else(|error| die(error));
+
+    sort_ref_infos(&mut tags, sort).unwrap_or_else(|error| die(error));
+
+    for info in &tags {
+        let lines = match lines {
+            Some(lines) => lines,
+            None => {
+                println!("{}", short_name(info));
+                continue;
+            }
+        };
+
+        // Like git, names are padded to 15 characters, and message lines
+        // after the first are indented.
+        let annotation = annotation(&repo, info);
+        let message = annotation
+            .lines()
+            .take(lines)
+            .collect::<Vec<_>>()
+            .join("\n    ");
+
+        println!("{:<15} {}", short_name(info), message);
+    }
+}
+
+fn verify_tag_command(names: &[String]) {
+    let repo = find_repository();
+    let mut failed = false;
+
+    for name in names {
+        match verify_tag(&repo, name) {
+            Ok((payload, messages)) => {
+                eprint!("{}", messages);
+                io::stdout()
+                    .write_all(&payload)
+                    .unwrap_or_else(|error| die(format!("Could not write tag: {}", error)));
+            }
+            Err(message) => {
+                error(message);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn update_server_info_command() {
+    let repo = find_repository();
+
+    update_server_info(&repo)
+        .unwrap_or_else(|error| die(format!("Could not update server info: {}", error)));
+}
+
 fn main() {
-    let cli = Cli::parse();
+    // Panics left in the library are reported as fatal errors, without a
+    // backtrace unless RUST_BACKTRACE asks for one.
+    if env::var_os("RUST_BACKTRACE").is_none() {
+        panic::set_hook(Box::new(|info| {
+            let message = match info.payload().downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match info.payload().downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => String::from("Unexpected error"),
+                },
+            };
+
+            die(message);
+        }));
+    }
+
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // --help and --version are not errors.
+        Err(error) if !error.use_stderr() => error.exit(),
+        Err(error) => {
+            let _ = error.print();
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    let output = Output {
+        verbosity: match (cli.quiet, cli.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, _) => Verbosity::Verbose,
+        },
+    };
+
+    // Set it in the environment so that it also applies to any process we spawn.
+    if cli.no_replace_objects {
+        env::set_var("GOT_NO_REPLACE_OBJECTS", "1");
+    }
 
     match &cli.command {
         Some(Commands::Add) => {}
         Some(Commands::CatFile {
+            exists,
             object_type,
             object,
         }) => {
-            let repo = match Repository::repo_find(env::current_dir().unwrap()) {
-                Some(repo) => repo,
-                None => panic!("Could not find repository"),
+            // With -e the only positional argument is the object.
+            let (object_type, object) = match (object_type, object) {
+                (Some(object), None) if *exists => (None, object),
+                (object_type, Some(object)) => (object_type.as_deref(), object),
+                _ => usage("got cat-file (<type> | -e) <object>"),
             };
 
-            let obj = repo
-                .object_read(object)
-                .expect("Could not find object with given hash");
+            cat_file_command(object_type, *exists, object);
+        }
+        Some(Commands::Checkout {
+            tree_ish,
+            force,
+            merge,
+            no_overlay,
+            paths,
+        }) => {
+            if paths.is_empty() {
+                if *no_overlay {
+                    usage("got checkout --no-overlay <tree-ish> -- <paths>");
+                }
+
+                switch_command(
+                    &output,
+                    tree_ish,
+                    &SwitchOptions {
+                        force: *force,
+                        merge: *merge,
+                    },
+                );
+            } else {
+                if *force || *merge {
+                    usage("got checkout [-f | -m] <branch>");
+                }
 
-            println!("{}", obj.serialise());
+                checkout_command(&output, tree_ish, !*no_overlay, paths);
+            }
         }
-        Some(Commands::Checkout) => {}
         Some(Commands::Commit) => {
-            commit_command();
+            commit_command(&output);
         }
-        Some(Commands::HashObject {
-            r#type,
-            write,
-            filepath,
+        Some(Commands::Completions { shell }) => {
+            completions_command(*shell);
+        }
+        Some(Commands::ForEachRef {
+            format,
+            sort,
+            count,
+            patterns,
         }) => {
+            for_each_ref_command(format, sort, *count, patterns);
+        }
+        Some(Commands::Gc { prune }) => {
+            gc_command(&output, prune.as_deref());
+        }
+        Some(Commands::HashObject { .. }) => {
             // TO BE DONE AGAIN
             // ================
             // let file_content = read_to_string(filepath).expect("Could not read file");
@@ -117,8 +1122,27 @@ fn main() {
             //     }
             // }
         }
-        Some(Commands::Init { path }) => {
-            Repository::create(PathBuf::from(path)).unwrap();
+        Some(Commands::Help { man, all, command }) => {
+            help_command(*man, *all, command.as_deref());
+        }
+        Some(Commands::Init {
+            template,
+            initial_branch,
+            path,
+        }) => {
+            let options = InitOptions {
+                template: template.as_ref().map(PathBuf::from),
+                initial_branch: initial_branch.clone(),
+            };
+
+            let repo =
+                Repository::init(PathBuf::from(path), &options).unwrap_or_else(|error| die(error));
+
+            let gotdir = repo.repo_path("");
+            output.info(format!(
+                "Initialized empty Got repository in {}/",
+                canonicalize(&gotdir).unwrap_or(gotdir).display()
+            ));
         }
         Some(Commands::Log) => {
             println!("Log");
@@ -129,9 +1153,56 @@ fn main() {
         Some(Commands::Merge) => {
             println!("Merge");
         }
+        Some(Commands::MergeFile {
+            labels,
+            ours,
+            theirs,
+            union,
+            stdout,
+            marker_size,
+            current,
+            base,
+            other,
+        }) => {
+            let favor = if *ours {
+                MergeFavor::Ours
+            } else if *theirs {
+                MergeFavor::Theirs
+            } else if *union {
+                MergeFavor::Union
+            } else {
+                MergeFavor::Normal
+            };
+
+            merge_file_command(labels, favor, *stdout, *marker_size, [current, base, other]);
+        }
+        Some(Commands::Mktag) => {
+            mktag_command();
+        }
+        Some(Commands::Mktree {
+            nul_terminated,
+            missing,
+        }) => {
+            mktree_command(*nul_terminated, *missing);
+        }
+        Some(Commands::NameRev {
+            tags,
+            name_only,
+            commits,
+        }) => {
+            name_rev_command(*tags, *name_only, commits);
+        }
         Some(Commands::Rebase) => {
             println!("Rebase");
         }
+        Some(Commands::Replace {
+            delete,
+            force,
+            list,
+            objects,
+        }) => {
+            replace_command(&output, *delete, *force, *list, objects);
+        }
         Some(Commands::RevParse) => {
             println!("RevParse");
         }
@@ -141,8 +1212,47 @@ fn main() {
         Some(Commands::ShowRef) => {
             println!("ShowRef");
         }
-        Some(Commands::Tag) => {
-            println!("Tag");
+        Some(Commands::Tag {
+            list,
+            contains,
+            sort,
+            lines,
+            verify,
+            names,
+            ..
+        }) => {
+            if *verify {
+                verify_tag_command(names);
+            } else if *list || contains.is_some() || !sort.is_empty() || lines.is_some() {
+                tag_list_command(names, contains.as_deref(), sort, *lines);
+            } else if names.is_empty() {
+                tag_list_command(&[], None, &[], None);
+            } else {
+                die("Creating tags is not implemented yet, use -l to list tags");
+            }
+        }
+        Some(Commands::UpdateServerInfo) => {
+            update_server_info_command();
+        }
+        Some(Commands::Var { variable }) => {
+            let repo = find_optional_repository();
+
+            match variable.as_str() {
+                "GOT_AUTHOR_IDENT" => println!(
+                    "{}",
+                    signature(repo.as_ref(), Role::Author).unwrap_or_else(|error| die(error))
+                ),
+                "GOT_COMMITTER_IDENT" => println!(
+                    "{}",
+                    signature(repo.as_ref(), Role::Committer).unwrap_or_else(|error| die(error))
+                ),
+                "GOT_DEFAULT_BRANCH" => println!(
+                    "{}",
+                    Repository::default_branch_name().unwrap_or_else(|error| die(error))
+                ),
+                "GOT_EDITOR" => println!("{}", editor(repo.as_ref())),
+                _ => die(format!("Unknown variable: {}", variable)),
+            }
         }
         _ => {}
     }
@@ -0,0 +1,793 @@
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    fs::{
+        create_dir_all, read, read_dir, read_link, remove_dir, remove_file, symlink_metadata, write,
+    },
+    iter::once,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    hooks::{Event, NULL_SHA},
+    merge_file::{merge_file, MergeFileOptions},
+    object::tree::{FileMode, Tree},
+    pathspec::Pathspec,
+    platform::{create_symlink, set_executable, validate_checkout_path},
+    repository::Repository,
+    validate::{validate_object_id, validate_tree_path},
+};
+
+// Writing commits and trees out to the worktree:
+//
+// - `got checkout <tree-ish> -- <paths>` restores some files from a commit or
+//   tree, leaving HEAD where it is.
+// - `got checkout <branch>` switches branches. As there is no index, a file
+//   counts as locally modified when it differs from the HEAD commit. Files
+//   that would be overwritten or deleted are only touched if they are not
+//   modified, unless forced (discarding the changes) or merged (carrying the
+//   changes over to the new version of the file).
+//
+// Trees with paths that would name the same file on a case-insensitive
+// filesystem (see Repository::ignore_case) are refused by both.
+
+// Every file of a tree, by path, with its mode and blob id. Submodules are
+// other repositories, which we do not check out, so they are left out.
+type TreeFiles = BTreeMap<String, (FileMode, String)>;
+
+// A file to write. Every file is read, and checked for untracked files in its
+// way, before any is written or deleted, so a missing or damaged object or an
+// untracked file does not leave the worktree half updated.
+struct CheckoutFile {
+    path: String,
+    mode: FileMode,
+    content: Vec<u8>,
+}
+
+fn tree_files(
+    repo: &Repository,
+    tree_sha: &str,
+    prefix: &str,
+    files: &mut TreeFiles,
+) -> Result<(), String> {
+    let (object_type, content) = repo.object_read_raw(tree_sha)?;
+
+    if object_type != "tree" {
+        return Err(format!(
+            "Object {} is a {}, not a tree",
+            tree_sha, object_type
+        ));
+    }
+
+    for entry in Tree::parse(&content)?.entries() {
+        let path = if prefix.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+
+        match entry.mode {
+            FileMode::Tree => tree_files(repo, &entry.oid.to_hex(), &path, files)?,
+            FileMode::Commit => {}
+            mode => {
+                validate_tree_path(&path)?;
+                validate_checkout_path(&path)?;
+
+                files.insert(path, (mode, entry.oid.to_hex()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Returns the files of the tree a tree-ish (a commit, a tag or a tree) points
+// to, or no files for None (an unborn branch).
+fn tree_ish_files(repo: &Repository, tree_ish: Option<&str>) -> Result<TreeFiles, String> {
+    let mut files = TreeFiles::new();
+
+    if let Some(tree_ish) = tree_ish {
+        tree_files(
+            repo,
+            &repo.object_find(tree_ish, Some("tree"))?,
+            "",
+            &mut files,
+        )?;
+    }
+
+    Ok(files)
+}
+
+// On a case-insensitive filesystem (core.ignorecase), paths differing only in
+// case name the same file, so checking out both would leave one overwriting
+// the other. Directories collide too ("Docs/a" and "docs/b").
+fn check_case_collisions<'a>(
+    repo: &Repository,
+    paths: impl Iterator<Item = &'a String>,
+) -> Result<(), String> {
+    if !repo.ignore_case() {
+        return Ok(());
+    }
+
+    let mut seen = HashMap::new();
+
+    for path in paths {
+        let ends = path.match_indices('/').map(|(index, _)| index);
+
+        for end in ends.chain(once(path.len())) {
+            let prefix = &path[..end];
+
+            match seen.entry(prefix.to_lowercase()) {
+                Entry::Occupied(entry) if *entry.get() != prefix => {
+                    return Err(format!(
+                        "Paths '{}' and '{}' collide on this case-insensitive filesystem",
+                        entry.get(),
+                        prefix
+                    ));
+                }
+                Entry::Occupied(_) => {}
+                Entry::Vacant(entry) => {
+                    entry.insert(prefix);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_blob(repo: &Repository, path: &str, sha: &str) -> Result<Vec<u8>, String> {
+    let (object_type, content) = repo.object_read_raw(sha)?;
+
+    if object_type != "blob" {
+        return Err(format!("Entry {:?} is a {}, not a blob", path, object_type));
+    }
+
+    Ok(content)
+}
+
+// Returns true if there is something in the worktree at a path that is not the
+// given entry: a file with other contents, a file where a symbolic link should
+// be (or the other way around), a directory, or anything at all where there
+// should be nothing. A missing file is not modified, as nothing would be lost
+// by writing it.
+fn is_modified(
+    repo: &Repository,
+    path: &str,
+    entry: Option<&(FileMode, String)>,
+) -> Result<bool, String> {
+    let worktree_path = repo.worktree().join(path);
+
+    let metadata = match symlink_metadata(&worktree_path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(false),
+    };
+
+    let (mode, sha) = match entry {
+        Some(entry) => entry,
+        None => return Ok(true),
+    };
+
+    let expected = read_blob(repo, path, sha)?;
+
+    let unchanged = match mode {
+        FileMode::Symlink => {
+            metadata.file_type().is_symlink()
+                && read_link(&worktree_path)
+                    .map(|target| target.to_string_lossy().as_bytes() == expected)
+                    .unwrap_or(false)
+        }
+        _ => {
+            metadata.is_file()
+                && read(&worktree_path)
+                    .map(|content| content == expected)
+                    .unwrap_or(false)
+        }
+    };
+
+    Ok(!unchanged)
+}
+
+// Returns the directory a worktree file goes in, checking that none of its
+// leading directories that exist is a file or a symbolic link. Links could
+// point out of the worktree, so we refuse to write or delete through them.
+fn parent_directory(worktree: &Path, path: &str) -> Result<PathBuf, String> {
+    let mut directory = worktree.to_path_buf();
+    let components = path.split('/').collect::<Vec<_>>();
+
+    for component in &components[..components.len() - 1] {
+        directory.push(component);
+
+        match symlink_metadata(&directory) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(format!("{:?} is beyond a symbolic link", path))
+            }
+            Ok(metadata) if !metadata.is_dir() => {
+                return Err(format!(
+                    "{:?} is in the way of {:?}, as it is not a directory",
+                    directory.strip_prefix(worktree).unwrap(),
+                    path
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(directory)
+}
+
+// Returns the paths of the files (and symbolic links) under a worktree
+// directory.
+fn files_in_directory(worktree: &Path, directory: &str) -> Vec<String> {
+    let mut files = vec![];
+
+    for entry in read_dir(worktree.join(directory))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let path = format!("{}/{}", directory, entry.file_name().to_string_lossy());
+
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                files.extend(files_in_directory(worktree, &path))
+            }
+            _ => files.push(path),
+        }
+    }
+
+    files
+}
+
+// Returns the untracked paths that would stop a file from being written once
+// the deleted files are gone: a file or symbolic link where one of its leading
+// directories should be, or files in a directory where it should be.
+fn obstacles(worktree: &Path, path: &str, deleted: &BTreeSet<&str>) -> Vec<String> {
+    let ends = path.match_indices('/').map(|(index, _)| index);
+
+    for end in ends {
+        let directory = &path[..end];
+
+        match symlink_metadata(worktree.join(directory)) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) if !deleted.contains(directory) => return vec![directory.to_owned()],
+            // Nothing is left further down once the file is deleted.
+            _ => return vec![],
+        }
+    }
+
+    match symlink_metadata(worktree.join(path)) {
+        Ok(metadata) if metadata.is_dir() => files_in_directory(worktree, path)
+            .into_iter()
+            .filter(|file| !deleted.contains(file.as_str()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// Returns the untracked paths in the way of any of the files to write, sorted.
+fn find_obstacles(worktree: &Path, files: &[CheckoutFile], deleted: &[String]) -> Vec<String> {
+    let deleted = deleted.iter().map(String::as_str).collect::<BTreeSet<_>>();
+
+    files
+        .iter()
+        .flat_map(|file| obstacles(worktree, &file.path, &deleted))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+// Removes a directory holding nothing but empty directories, as deleting files
+// can leave behind.
+fn remove_empty_directory(path: &Path) -> std::io::Result<()> {
+    for entry in read_dir(path)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            remove_empty_directory(&entry.path())?;
+        }
+    }
+
+    remove_dir(path)
+}
+
+fn write_file(worktree: &Path, file: &CheckoutFile) -> Result<(), String> {
+    let directory = parent_directory(worktree, &file.path)?;
+    let io_error = |error: std::io::Error| format!("Cannot write {:?}: {}", file.path, error);
+
+    create_dir_all(&directory).map_err(io_error)?;
+    let path = worktree.join(&file.path);
+
+    // Files and links in the way are replaced, but not directories, which may
+    // hold files that are not in the tree, unless they are empty.
+    match symlink_metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => remove_empty_directory(&path).map_err(|_| {
+            format!(
+                "Cannot write {:?}, there is a directory in its place",
+                file.path
+            )
+        })?,
+        Ok(_) => remove_file(&path).map_err(io_error)?,
+        Err(_) => {}
+    }
+
+    match file.mode {
+        FileMode::Symlink => create_symlink(&String::from_utf8_lossy(&file.content), &path),
+        FileMode::Executable => {
+            write(&path, &file.content).and_then(|()| set_executable(&path, true))
+        }
+        _ => write(&path, &file.content),
+    }
+    .map_err(io_error)
+}
+
+// Removes a file from the worktree, and then its leading directories that were
+// left empty.
+fn delete_file(worktree: &Path, path: &str) -> Result<(), String> {
+    parent_directory(worktree, path)?;
+    let worktree_path = worktree.join(path);
+
+    match remove_file(&worktree_path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(format!("Cannot delete {:?}: {}", path, error)),
+    }
+
+    for directory in worktree_path.ancestors().skip(1) {
+        if directory == worktree || remove_dir(directory).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Writes the files of a tree-ish matching the pathspec into the worktree,
+// returning the paths updated, and notifies listeners of a checkout event if
+// there were any. Without overlay, tracked files matching the pathspec that
+// are not in the tree are deleted, so the matching part of the worktree ends
+// up exactly as in the tree.
+pub fn checkout_paths(
+    repo: &Repository,
+    tree_ish: &str,
+    pathspec: &Pathspec,
+    overlay: bool,
+) -> Result<Vec<String>, String> {
+    let mut files = vec![];
+
+    for (path, (mode, sha)) in tree_ish_files(repo, Some(tree_ish))? {
+        if pathspec.matches(&path) {
+            files.push(CheckoutFile {
+                content: read_blob(repo, &path, &sha)?,
+                path,
+                mode,
+            });
+        }
+    }
+
+    check_case_collisions(repo, files.iter().map(|file| &file.path))?;
+
+    let mut deleted = vec![];
+
+    // As there is no index, the files tracked are those of HEAD. Untracked
+    // files are never deleted.
+    if !overlay {
+        let in_tree = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<BTreeSet<_>>();
+
+        let head = repo.ref_read("HEAD");
+
+        for path in tree_ish_files(repo, head.as_deref())?.into_keys() {
+            if pathspec.matches(&path)
+                && !in_tree.contains(path.as_str())
+                && symlink_metadata(repo.worktree().join(&path)).is_ok()
+            {
+                deleted.push(path);
+            }
+        }
+    }
+
+    let untracked = find_obstacles(repo.worktree(), &files, &deleted);
+
+    if !untracked.is_empty() {
+        return Err(blocked_error(&[], &untracked, "check out these paths"));
+    }
+
+    // Deleting first lets a file be replaced by a directory of the same name.
+    for path in &deleted {
+        delete_file(repo.worktree(), path)?;
+    }
+
+    for file in &files {
+        write_file(repo.worktree(), file)?;
+    }
+
+    let mut paths = files.into_iter().map(|file| file.path).collect::<Vec<_>>();
+    paths.extend(deleted);
+
+    if !paths.is_empty() {
+        let head = repo.ref_read("HEAD").unwrap_or_else(|| NULL_SHA.to_owned());
+
+        repo.notify(&Event::Checkout {
+            old: head.clone(),
+            new: head,
+            branch_switch: false,
+        });
+    }
+
+    Ok(paths)
+}
+
+#[derive(Default)]
+pub struct SwitchOptions {
+    // Discard local changes to files that differ between the two commits.
+    pub force: bool,
+    // Merge local changes to files that differ between the two commits into
+    // their new version, leaving conflict markers where they cannot be.
+    pub merge: bool,
+}
+
+#[derive(Debug)]
+pub struct SwitchResult {
+    // Branch HEAD now points to, or None if it was detached.
+    pub branch: Option<String>,
+    pub commit: String,
+    // Files whose local changes were merged with conflicts.
+    pub conflicts: Vec<String>,
+}
+
+// Builds the error listing the files that block a checkout, as git words it.
+fn blocked_error(modified: &[String], untracked: &[String], action: &str) -> String {
+    let mut message = String::new();
+
+    if !modified.is_empty() {
+        message.push_str(
+            "Your local changes to the following files would be overwritten by checkout:\n",
+        );
+
+        for path in modified {
+            message.push_str(&format!("\t{}\n", path));
+        }
+
+        message.push_str(&format!(
+            "Please commit your changes or stash them before you {}.\n",
+            action
+        ));
+    }
+
+    if !untracked.is_empty() {
+        message.push_str(
+            "The following untracked working tree files would be overwritten by checkout:\n",
+        );
+
+        for path in untracked {
+            message.push_str(&format!("\t{}\n", path));
+        }
+
+        message.push_str(&format!(
+            "Please move or remove them before you {}.\n",
+            action
+        ));
+    }
+
+    message.push_str("Aborting");
+
+    message
+}
+
+// Merges the local changes to a file into its version in the target commit.
+// Returns None if the file cannot be merged (a symbolic link, binary content,
+// or a file missing from either commit).
+fn merge_local_changes(
+    repo: &Repository,
+    path: &str,
+    old: Option<&(FileMode, String)>,
+    new: Option<&(FileMode, String)>,
+    target: &str,
+) -> Result<Option<(CheckoutFile, usize)>, String> {
+    let (old_mode, old_sha, new_mode, new_sha) = match (old, new) {
+        (Some((old_mode, old_sha)), Some((new_mode, new_sha))) => {
+            (old_mode, old_sha, *new_mode, new_sha)
+        }
+        _ => return Ok(None),
+    };
+
+    if *old_mode == FileMode::Symlink || new_mode == FileMode::Symlink {
+        return Ok(None);
+    }
+
+    let local = read(repo.worktree().join(path)).ok();
+    let base = read_blob(repo, path, old_sha)?;
+    let theirs = read_blob(repo, path, new_sha)?;
+
+    let (local, base, theirs) = match (
+        local.as_deref().map(std::str::from_utf8),
+        std::str::from_utf8(&base),
+        std::str::from_utf8(&theirs),
+    ) {
+        (Some(Ok(local)), Ok(base), Ok(theirs)) => (local, base, theirs),
+        _ => return Ok(None),
+    };
+
+    let options = MergeFileOptions {
+        ours_label: String::from("local"),
+        theirs_label: target.to_owned(),
+        ..MergeFileOptions::default()
+    };
+
+    let result = merge_file(local, base, theirs, &options);
+
+    Ok(Some((
+        CheckoutFile {
+            path: path.to_owned(),
+            mode: new_mode,
+            content: result.content.into_bytes(),
+        },
+        result.conflicts,
+    )))
+}
+
+// Switches the worktree and HEAD to a branch (given by name) or to any other
+// commit (detaching HEAD). Only files that differ between HEAD and the target
+// are touched, unless forcing, which also resets files modified locally. A
+// Listeners are notified of a checkout event once HEAD is updated.
+pub fn switch(
+    repo: &Repository,
+    target: &str,
+    options: &SwitchOptions,
+) -> Result<SwitchResult, String> {
+    // Branches win over tags of the same name.
+    let branch = match validate_object_id(target) {
+        Ok(()) => None,
+        Err(_) => [target.to_owned(), format!("refs/heads/{}", target)]
+            .into_iter()
+            .find(|name| name.starts_with("refs/heads/") && repo.ref_read(name).is_some()),
+    };
+
+    let commit = repo.object_find(branch.as_deref().unwrap_or(target), Some("commit"))?;
+    let head = repo.ref_read("HEAD");
+
+    let old_files = tree_ish_files(repo, head.as_deref())?;
+    let new_files = tree_ish_files(repo, Some(&commit))?;
+
+    check_case_collisions(repo, new_files.keys())?;
+
+    let mut modified = vec![];
+    let mut untracked = vec![];
+    let mut writes = vec![];
+    let mut deletes = vec![];
+    let mut conflicts = vec![];
+
+    let paths = old_files
+        .keys()
+        .chain(new_files.keys())
+        .collect::<BTreeSet<_>>();
+
+    for path in paths {
+        let old = old_files.get(path);
+        let new = new_files.get(path);
+
+        let changed = old != new;
+
+        if !changed && !options.force {
+            continue;
+        }
+
+        // A file already as in the target needs nothing, whatever HEAD had.
+        if new.is_some()
+            && symlink_metadata(repo.worktree().join(path)).is_ok()
+            && !is_modified(repo, path, new)?
+        {
+            continue;
+        }
+
+        if changed && !options.force && is_modified(repo, path, old)? {
+            if old.is_none() {
+                untracked.push(path.clone());
+                continue;
+            }
+
+            let merged = match options.merge {
+                true => merge_local_changes(repo, path, old, new, target)?,
+                false => None,
+            };
+
+            match merged {
+                Some((file, file_conflicts)) => {
+                    if file_conflicts > 0 {
+                        conflicts.push(path.clone());
+                    }
+
+                    writes.push(file);
+                }
+                None => modified.push(path.clone()),
+            }
+
+            continue;
+        }
+
+        match new {
+            Some((mode, sha)) => writes.push(CheckoutFile {
+                path: path.clone(),
+                mode: *mode,
+                content: read_blob(repo, path, sha)?,
+            }),
+            None => deletes.push(path.clone()),
+        }
+    }
+
+    // Untracked files in the way are looked for before touching anything, even
+    // when forcing, so a switch either fully applies or changes nothing.
+    untracked.extend(find_obstacles(repo.worktree(), &writes, &deletes));
+    untracked.sort();
+    untracked.dedup();
+
+    if !modified.is_empty() || !untracked.is_empty() {
+        return Err(blocked_error(&modified, &untracked, "switch branches"));
+    }
+
+    // Deleting first lets a file be replaced by a directory of the same name.
+    for path in &deletes {
+        delete_file(repo.worktree(), path)?;
+    }
+
+    for file in &writes {
+        write_file(repo.worktree(), file)?;
+    }
+
+    match &branch {
+        Some(branch) => repo.head_attach(branch)?,
+        None => repo.head_detach(&commit)?,
+    }
+
+    repo.notify(&Event::Checkout {
+        old: head.unwrap_or_else(|| NULL_SHA.to_owned()),
+        new: commit.clone(),
+        branch_switch: true,
+    });
+
+    Ok(SwitchResult {
+        branch,
+        commit,
+        conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRepository;
+
+    // A repository on branch base, whose m.txt topic changes, also adding z/b.
+    fn base_and_topic(name: &str) -> TestRepository {
+        let test = TestRepository::new(name);
+        let base = test.commit(&[("m.txt", FileMode::Blob, "base\n")], &[]);
+        let topic = test.commit(
+            &[
+                ("m.txt", FileMode::Blob, "topic\n"),
+                ("z/b", FileMode::Blob, "b\n"),
+            ],
+            &[&base],
+        );
+
+        test.repo.ref_write("refs/heads/base", &base).unwrap();
+        test.repo.ref_write("refs/heads/topic", &topic).unwrap();
+        test.repo.head_attach("refs/heads/base").unwrap();
+        test.write_file("m.txt", "base\n");
+
+        test
+    }
+
+    #[test]
+    fn switch_updates_files_and_head() {
+        let test = base_and_topic("switch_updates_files_and_head");
+
+        let result = switch(&test.repo, "topic", &SwitchOptions::default()).unwrap();
+
+        assert_eq!(result.branch.as_deref(), Some("refs/heads/topic"));
+        assert_eq!(test.read_file("m.txt").as_deref(), Some("topic\n"));
+        assert_eq!(test.read_file("z/b").as_deref(), Some("b\n"));
+        assert_eq!(test.repo.head_branch().as_deref(), Some("refs/heads/topic"));
+    }
+
+    #[test]
+    fn switch_changes_nothing_with_untracked_files_in_the_way() {
+        let test = base_and_topic("switch_untracked_in_the_way");
+        test.write_file("z", "untracked\n");
+
+        for force in [false, true] {
+            let options = SwitchOptions {
+                force,
+                ..SwitchOptions::default()
+            };
+            let error = switch(&test.repo, "topic", &options).unwrap_err();
+
+            assert!(error.contains("untracked working tree files"), "{}", error);
+            assert!(error.contains("\tz\n"), "{}", error);
+            assert_eq!(test.read_file("m.txt").as_deref(), Some("base\n"));
+            assert_eq!(test.read_file("z").as_deref(), Some("untracked\n"));
+            assert_eq!(test.repo.head_branch().as_deref(), Some("refs/heads/base"));
+        }
+    }
+
+    #[test]
+    fn switch_changes_nothing_with_untracked_files_in_a_directory_in_the_way() {
+        let test = base_and_topic("switch_untracked_directory");
+        test.write_file("z/b/untracked", "untracked\n");
+
+        for force in [false, true] {
+            let options = SwitchOptions {
+                force,
+                ..SwitchOptions::default()
+            };
+            let error = switch(&test.repo, "topic", &options).unwrap_err();
+
+            assert!(error.contains("\tz/b"), "{}", error);
+            assert_eq!(test.read_file("m.txt").as_deref(), Some("base\n"));
+            assert_eq!(
+                test.read_file("z/b/untracked").as_deref(),
+                Some("untracked\n")
+            );
+        }
+    }
+
+    #[test]
+    fn switch_refuses_to_overwrite_local_changes() {
+        let test = base_and_topic("switch_refuses_local_changes");
+        test.write_file("m.txt", "local\n");
+
+        let error = switch(&test.repo, "topic", &SwitchOptions::default()).unwrap_err();
+
+        assert!(error.contains("Your local changes"), "{}", error);
+        assert!(error.contains("\tm.txt\n"), "{}", error);
+        assert_eq!(test.read_file("m.txt").as_deref(), Some("local\n"));
+        assert_eq!(test.read_file("z/b"), None);
+        assert_eq!(test.repo.head_branch().as_deref(), Some("refs/heads/base"));
+    }
+
+    #[test]
+    fn forced_switch_discards_local_changes() {
+        let test = base_and_topic("forced_switch_discards_local_changes");
+        test.write_file("m.txt", "local\n");
+
+        let options = SwitchOptions {
+            force: true,
+            ..SwitchOptions::default()
+        };
+        switch(&test.repo, "topic", &options).unwrap();
+
+        assert_eq!(test.read_file("m.txt").as_deref(), Some("topic\n"));
+        assert_eq!(test.repo.head_branch().as_deref(), Some("refs/heads/topic"));
+    }
+
+    #[test]
+    fn checkout_paths_changes_nothing_with_untracked_files_in_the_way() {
+        let test = base_and_topic("checkout_paths_untracked_in_the_way");
+        test.write_file("z", "untracked\n");
+
+        let pathspec = Pathspec::parse(&[]).unwrap();
+        let error = checkout_paths(&test.repo, "topic", &pathspec, true).unwrap_err();
+
+        assert!(error.contains("\tz\n"), "{}", error);
+        assert_eq!(test.read_file("m.txt").as_deref(), Some("base\n"));
+        assert_eq!(test.read_file("z").as_deref(), Some("untracked\n"));
+    }
+
+    #[test]
+    fn checkout_paths_without_overlay_only_deletes_tracked_files() {
+        let test = base_and_topic("checkout_paths_without_overlay");
+        let pathspec = Pathspec::parse(&[]).unwrap();
+
+        switch(&test.repo, "topic", &SwitchOptions::default()).unwrap();
+        test.write_file("untracked", "untracked\n");
+
+        let mut paths = checkout_paths(&test.repo, "base", &pathspec, false).unwrap();
+        paths.sort();
+
+        assert_eq!(paths, ["m.txt", "z/b"]);
+        assert_eq!(test.read_file("m.txt").as_deref(), Some("base\n"));
+        assert_eq!(test.read_file("z/b"), None);
+        assert_eq!(test.read_file("untracked").as_deref(), Some("untracked\n"));
+    }
+}
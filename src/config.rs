@@ -0,0 +1,37 @@
+use std::{env, fs::read_to_string, path::PathBuf};
+
+use configparser::ini::Ini;
+
+// Configuration shared by every repository of the user, for settings that have
+// to be known before a repository exists (like init.templateDir). It is read
+// from $GOT_CONFIG_GLOBAL if set, or from ~/.gotconfig.
+
+pub fn global_config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("GOT_CONFIG_GLOBAL") {
+        return Some(PathBuf::from(path));
+    }
+
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".gotconfig"))
+}
+
+// Returns the global configuration, empty if there is none. A malformed file is
+// reported rather than silently ignored.
+pub fn global_config() -> Result<Ini, String> {
+    let mut config = Ini::new();
+
+    if let Some(content) = global_config_path().and_then(|path| read_to_string(path).ok()) {
+        config
+            .read(content)
+            .map_err(|error| format!("Global configuration file has wrong format: {}", error))?;
+    }
+
+    Ok(config)
+}
+
+pub fn global_config_get(section: &str, key: &str) -> Result<Option<String>, String> {
+    Ok(global_config()?
+        .get(section, key)
+        .filter(|value| !value.is_empty()))
+}
@@ -0,0 +1,722 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::timezone::{local_offset_minutes, Timezone};
+
+// Parsing and formatting of the `<timestamp> <timezone>` dates stored in commit
+// and tag signatures (e.g. `1666772992 +0100`), and of the dates users type in
+// options like --since ("2 weeks ago", "2022-10-26").
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// Latest year accepted in ISO dates. Bounding it keeps the arithmetic turning
+// dates into timestamps far from overflowing.
+const MAX_YEAR: i64 = 9999;
+
+// Seconds in each unit accepted by relative dates ("3 weeks ago"). Months and
+// years are approximated, as git does.
+const TIME_UNITS: [(&str, i64); 7] = [
+    ("second", 1),
+    ("minute", 60),
+    ("hour", 60 * 60),
+    ("day", 24 * 60 * 60),
+    ("week", 7 * 24 * 60 * 60),
+    ("month", 30 * 24 * 60 * 60),
+    ("year", 365 * 24 * 60 * 60),
+];
+
+// The ways a date can be shown, as accepted by `--date=<format>` and by the
+// date placeholder modifiers of for-each-ref.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    Default,
+    Relative,
+    Iso,
+    IsoStrict,
+    Rfc2822,
+    Short,
+    Raw,
+    Unix,
+    // strftime-like format string, e.g. `format:%Y-%m-%d`.
+    Custom(String),
+}
+
+impl DateFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        if let Some(format) = name.strip_prefix("format:") {
+            return Ok(DateFormat::Custom(format.to_owned()));
+        }
+
+        match name {
+            "default" => Ok(DateFormat::Default),
+            "relative" => Ok(DateFormat::Relative),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(DateFormat::IsoStrict),
+            "rfc" | "rfc2822" => Ok(DateFormat::Rfc2822),
+            "short" => Ok(DateFormat::Short),
+            "raw" => Ok(DateFormat::Raw),
+            "unix" => Ok(DateFormat::Unix),
+            _ => Err(format!("Unknown date format: {:?}", name)),
+        }
+    }
+}
+
+// A point in time as stored in objects: seconds since the unix epoch plus the
+// offset of the timezone it was recorded in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Date {
+    pub timestamp: i64,
+    pub offset_minutes: i32,
+}
+
+// Calendar fields of a date in its own timezone.
+struct Civil {
+    year: i64,
+    month: usize,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    weekday: usize,
+    // Starting at 1 for January 1st.
+    day_of_year: i64,
+}
+
+// Converts days since the unix epoch into a (year, month, day) triple.
+// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub(crate) fn civil_from_days(days: i64) -> (i64, usize, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month as usize, day)
+}
+
+// The inverse of civil_from_days.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+
+    days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn plural(count: i64, unit: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+// Parses a `+hhmm`, `+hh:mm`, `+hh` or `Z` timezone into minutes.
+fn parse_timezone(timezone: &str) -> Option<i32> {
+    if timezone == "Z" {
+        return Some(0);
+    }
+
+    let sign = match timezone.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = timezone[1..].replace(':', "");
+
+    if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse::<i32>().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+
+    if minutes >= 60 {
+        return None;
+    }
+
+    Some(sign * (hours * 60 + minutes))
+}
+
+// Parses a number made only of ASCII digits (parse would also take a sign).
+fn parse_digits(digits: &str) -> Option<i64> {
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    digits.parse().ok()
+}
+
+// Parses `YYYY-MM-DD`, optionally followed by ` HH:MM[:SS]` or `THH:MM[:SS]`
+// and a timezone. Dates without a timezone are in the local one.
+fn parse_iso(input: &str) -> Option<Date> {
+    let (date, rest) = match input.find([' ', 'T']) {
+        Some(index) => (&input[..index], input[index + 1..].trim()),
+        None => (input, ""),
+    };
+
+    let [year, month, day] = date.split('-').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let (year, month, day) = (
+        parse_digits(year)?,
+        parse_digits(month)?,
+        parse_digits(day)?,
+    );
+
+    if year > MAX_YEAR
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+    {
+        return None;
+    }
+
+    // The timezone may be separated by a space or glued to the time
+    // (`09:29:52+01:00`, `09:29:52Z`).
+    let (time, timezone) = match rest.find([' ', '+', '-', 'Z']) {
+        Some(index) => (&rest[..index], rest[index..].trim()),
+        None => (rest, ""),
+    };
+
+    let (hour, minute, second) = match time.split(':').collect::<Vec<_>>()[..] {
+        [] | [""] => (0, 0, 0),
+        [hour, minute] => (parse_digits(hour)?, parse_digits(minute)?, 0),
+        [hour, minute, second] => (
+            parse_digits(hour)?,
+            parse_digits(minute)?,
+            parse_digits(second)?,
+        ),
+        _ => return None,
+    };
+
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let local = days_from_civil(year, month, day)
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+
+    if timezone.is_empty() {
+        return Some(Date::local_time(local));
+    }
+
+    let offset_minutes = parse_timezone(timezone)?;
+
+    Some(Date {
+        timestamp: local.checked_sub(offset_minutes as i64 * 60)?,
+        offset_minutes,
+    })
+}
+
+// Parses relative dates: "now", "yesterday" and "<n> <unit>[s] ago", where
+// words may also be separated by dots ("2.weeks.ago").
+fn parse_relative(input: &str, now: i64) -> Option<Date> {
+    let words = input
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+
+    let seconds_ago = match &words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["now"] => 0,
+        ["yesterday"] => 24 * 60 * 60,
+        [count, unit, "ago"] => {
+            let count = parse_digits(count)?;
+            let unit = unit.strip_suffix('s').unwrap_or(unit);
+            let (_, seconds) = TIME_UNITS.iter().find(|(name, _)| *name == unit)?;
+
+            count.checked_mul(*seconds)?
+        }
+        _ => return None,
+    };
+
+    Some(Date::local(now.checked_sub(seconds_ago)?))
+}
+
+impl Date {
+    // Returns a timestamp in the local timezone.
+    pub fn local(timestamp: i64) -> Self {
+        Date {
+            timestamp,
+            offset_minutes: local_offset_minutes(timestamp),
+        }
+    }
+
+    pub fn now() -> Self {
+        Date::local(now())
+    }
+
+    // Returns the date whose local wall clock time, given as seconds since the
+    // unix epoch, is `local`. The offset is taken at the UTC reading of it and
+    // then at the corrected time, which settles on the right side of a daylight
+    // saving time change except within the skipped or repeated hour.
+    fn local_time(local: i64) -> Self {
+        Date::local_time_in(local, Timezone::local())
+    }
+
+    fn local_time_in(local: i64, timezone: &Timezone) -> Self {
+        let offset = timezone.offset_minutes(local) as i64 * 60;
+        let offset = timezone.offset_minutes(local - offset) as i64 * 60;
+        let timestamp = local - offset;
+
+        Date {
+            timestamp,
+            offset_minutes: timezone.offset_minutes(timestamp),
+        }
+    }
+
+    // Parses the `<timestamp> <+/-hhmm>` pair found at the end of signatures.
+    pub fn parse_raw(raw: &str) -> Option<Self> {
+        let (timestamp, timezone) = raw.trim().split_once(' ')?;
+        let timestamp = timestamp.parse::<i64>().ok()?;
+
+        let sign = match timezone.get(..1)? {
+            "+" => 1,
+            "-" => -1,
+            _ => return None,
+        };
+        let digits = timezone.get(1..)?;
+
+        if digits.len() != 4 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        let hours: i32 = digits[..2].parse().ok()?;
+        let minutes: i32 = digits[2..].parse().ok()?;
+
+        Some(Date {
+            timestamp,
+            offset_minutes: sign * (hours * 60 + minutes),
+        })
+    }
+
+    // Parses a date as typed by users, relative to the current time. Accepts
+    // raw dates (`1666772992 +0100`), unix timestamps (`@1666772992`), ISO 8601
+    // dates and relative ones (`3 days ago`).
+    pub fn parse(input: &str) -> Option<Self> {
+        Date::parse_relative_to(input, now())
+    }
+
+    pub fn parse_relative_to(input: &str, now: i64) -> Option<Self> {
+        let input = input.trim();
+
+        if let Some(timestamp) = input.strip_prefix('@') {
+            return Some(Date {
+                timestamp: parse_digits(timestamp)?,
+                offset_minutes: 0,
+            });
+        }
+
+        Date::parse_raw(input)
+            .or_else(|| parse_iso(input))
+            .or_else(|| parse_relative(input, now))
+    }
+
+    fn timezone(&self) -> String {
+        let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+        let offset = self.offset_minutes.abs();
+
+        format!("{}{:02}{:02}", sign, offset / 60, offset % 60)
+    }
+
+    fn civil(&self) -> Civil {
+        // Raw dates read from objects can hold any timestamp, so the offset is
+        // added without overflowing near the ends of the range.
+        let local = self.timestamp as i128 + self.offset_minutes as i128 * 60;
+        let days = local.div_euclid(86400) as i64;
+        let seconds = local.rem_euclid(86400) as i64;
+        let (year, month, day) = civil_from_days(days);
+
+        Civil {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds % 3600 / 60,
+            second: seconds % 60,
+            // 1970-01-01 was a Thursday.
+            weekday: (days + 4).rem_euclid(7) as usize,
+            day_of_year: days - days_from_civil(year, 1, 1) + 1,
+        }
+    }
+
+    pub fn format(&self, format: &DateFormat) -> String {
+        match format {
+            DateFormat::Default => self.format_default(),
+            DateFormat::Relative => self.format_relative(now()),
+            DateFormat::Iso => self.format_custom("%Y-%m-%d %H:%M:%S %z"),
+            DateFormat::IsoStrict => {
+                let timezone = self.timezone();
+
+                format!(
+                    "{}{}:{}",
+                    self.format_custom("%Y-%m-%dT%H:%M:%S"),
+                    &timezone[..3],
+                    &timezone[3..]
+                )
+            }
+            DateFormat::Rfc2822 => self.format_custom("%a, %d %b %Y %H:%M:%S %z"),
+            DateFormat::Short => self.format_custom("%Y-%m-%d"),
+            DateFormat::Raw => self.format_raw(),
+            DateFormat::Unix => self.timestamp.to_string(),
+            DateFormat::Custom(format) => self.format_custom(format),
+        }
+    }
+
+    // How long ago the date was compared to now, rounded like git does:
+    // `5 minutes ago`, `3 weeks ago`, `2 years, 3 months ago`.
+    pub fn format_relative(&self, now: i64) -> String {
+        if self.timestamp > now {
+            return String::from("in the future");
+        }
+
+        let seconds = now.saturating_sub(self.timestamp);
+        if seconds < 90 {
+            return format!("{} ago", plural(seconds, "second"));
+        }
+
+        let minutes = seconds.saturating_add(30) / 60;
+        if minutes < 90 {
+            return format!("{} ago", plural(minutes, "minute"));
+        }
+
+        let hours = (minutes + 30) / 60;
+        if hours < 36 {
+            return format!("{} ago", plural(hours, "hour"));
+        }
+
+        let days = (hours + 12) / 24;
+        if days < 14 {
+            return format!("{} ago", plural(days, "day"));
+        }
+
+        if days < 70 {
+            return format!("{} ago", plural((days + 3) / 7, "week"));
+        }
+
+        if days < 365 {
+            return format!("{} ago", plural((days + 15) / 30, "month"));
+        }
+
+        if days < 1825 {
+            let total_months = (days * 12 * 2 + 365) / (365 * 2);
+            let (years, months) = (total_months / 12, total_months % 12);
+
+            if months > 0 {
+                return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+            }
+
+            return format!("{} ago", plural(years, "year"));
+        }
+
+        format!("{} ago", plural((days + 183) / 365, "year"))
+    }
+
+    // Expands strftime-like conversions, in the date's own timezone. %Z is
+    // left empty, as the timezone name is not known.
+    pub fn format_custom(&self, format: &str) -> String {
+        let civil = self.civil();
+        let mut output = String::new();
+        let mut chars = format.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+
+            let hour12 = if civil.hour % 12 == 0 {
+                12
+            } else {
+                civil.hour % 12
+            };
+
+            match chars.next() {
+                Some('a') => output.push_str(WEEKDAYS[civil.weekday]),
+                Some('A') => output.push_str(WEEKDAY_NAMES[civil.weekday]),
+                Some('b') | Some('h') => output.push_str(MONTHS[civil.month - 1]),
+                Some('B') => output.push_str(MONTH_NAMES[civil.month - 1]),
+                Some('d') => output.push_str(&format!("{:02}", civil.day)),
+                Some('e') => output.push_str(&format!("{:>2}", civil.day)),
+                Some('F') => output.push_str(&self.format_custom("%Y-%m-%d")),
+                Some('H') => output.push_str(&format!("{:02}", civil.hour)),
+                Some('I') => output.push_str(&format!("{:02}", hour12)),
+                Some('j') => output.push_str(&format!("{:03}", civil.day_of_year)),
+                Some('m') => output.push_str(&format!("{:02}", civil.month)),
+                Some('M') => output.push_str(&format!("{:02}", civil.minute)),
+                Some('p') => output.push_str(if civil.hour < 12 { "AM" } else { "PM" }),
+                Some('R') => output.push_str(&self.format_custom("%H:%M")),
+                Some('s') => output.push_str(&self.timestamp.to_string()),
+                Some('S') => output.push_str(&format!("{:02}", civil.second)),
+                Some('T') => output.push_str(&self.format_custom("%H:%M:%S")),
+                Some('u') => output.push_str(&(((civil.weekday + 6) % 7) + 1).to_string()),
+                Some('w') => output.push_str(&civil.weekday.to_string()),
+                Some('y') => output.push_str(&format!("{:02}", civil.year.rem_euclid(100))),
+                Some('Y') => output.push_str(&civil.year.to_string()),
+                Some('z') => output.push_str(&self.timezone()),
+                Some('Z') => {}
+                Some('%') => output.push('%'),
+                Some(other) => {
+                    output.push('%');
+                    output.push(other);
+                }
+                None => output.push('%'),
+            }
+        }
+
+        output
+    }
+
+    // `1666772992 +0100`
+    pub fn format_raw(&self) -> String {
+        format!("{} {}", self.timestamp, self.timezone())
+    }
+
+    // Git's default format: `Wed Oct 26 09:29:52 2022 +0100`
+    pub fn format_default(&self) -> String {
+        let civil = self.civil();
+
+        format!(
+            "{} {} {} {:02}:{:02}:{:02} {} {}",
+            WEEKDAYS[civil.weekday],
+            MONTHS[civil.month - 1],
+            civil.day,
+            civil.hour,
+            civil.minute,
+            civil.second,
+            civil.year,
+            self.timezone()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values are the output of git for-each-ref on a commit with the
+    // same committer date.
+    const DATE: Date = Date {
+        timestamp: 1666772992,
+        offset_minutes: 60,
+    };
+
+    #[test]
+    fn formats_like_git() {
+        let cases = [
+            ("default", "Wed Oct 26 09:29:52 2022 +0100"),
+            ("iso", "2022-10-26 09:29:52 +0100"),
+            ("iso-strict", "2022-10-26T09:29:52+01:00"),
+            ("rfc", "Wed, 26 Oct 2022 09:29:52 +0100"),
+            ("short", "2022-10-26"),
+            ("raw", "1666772992 +0100"),
+            ("unix", "1666772992"),
+            (
+                "format:%a %A %b %B %d %e %H %I %j %m %M %p %S %u %w %y %Y %z %%",
+                "Wed Wednesday Oct October 26 26 09 09 299 10 29 AM 52 3 3 22 2022 +0100 %",
+            ),
+        ];
+
+        for (format, expected) in cases {
+            assert_eq!(DATE.format(&DateFormat::parse(format).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn formats_relative_like_git() {
+        let cases = [
+            (1666773000, "8 seconds ago"),
+            (1666780000, "2 hours ago"),
+            (1666900000, "35 hours ago"),
+            (1668000000, "2 weeks ago"),
+            (1670000000, "5 weeks ago"),
+            (1690000000, "9 months ago"),
+            (1740000000, "2 years, 4 months ago"),
+            (1900000000, "7 years ago"),
+            (1666772000, "in the future"),
+        ];
+
+        for (now, expected) in cases {
+            assert_eq!(DATE.format_relative(now), expected);
+        }
+    }
+
+    #[test]
+    fn parses_dates_with_timezones() {
+        let cases = [
+            ("1666772992 +0100", 1666772992, 60),
+            ("@1666772992", 1666772992, 0),
+            ("2022-10-26 09:29:52 +0100", 1666772992, 60),
+            ("2022-10-26T09:29:52+01:00", 1666772992, 60),
+            ("2022-10-26T08:29:52Z", 1666772992, 0),
+            ("2022-10-26 02:59:52 -05:30", 1666772992, -330),
+            ("2024-02-29 00:00 +0000", 1709164800, 0),
+        ];
+
+        for (input, timestamp, offset_minutes) in cases {
+            assert_eq!(
+                Date::parse(input),
+                Some(Date {
+                    timestamp,
+                    offset_minutes
+                }),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        let now = 1666772992;
+
+        for (input, seconds_ago) in [
+            ("now", 0),
+            ("yesterday", 86400),
+            ("3 weeks ago", 3 * 7 * 86400),
+            ("2.days.ago", 2 * 86400),
+            ("1 HOUR AGO", 3600),
+        ] {
+            assert_eq!(
+                Date::parse_relative_to(input, now).map(|date| date.timestamp),
+                Some(now - seconds_ago),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for input in [
+            "",
+            "tomorrow",
+            "2022-13-01 +0000",
+            "2022-02-29 +0000",
+            "2022-04-31 +0000",
+            "2022-10-26 24:00 +0000",
+            "2022-10-26 09:60 +0000",
+            "2022-10-26 09:29 +0160",
+            "1666772992 0100",
+            "99999999999999999-01-01",
+            "10000-01-01 +0000",
+            "99999999999999999 weeks ago",
+        ] {
+            assert_eq!(Date::parse_relative_to(input, 0), None, "{}", input);
+        }
+    }
+
+    // Dates without a timezone are in the local one, whose offset depends on
+    // the date itself around daylight saving time changes.
+    #[test]
+    fn resolves_local_times_around_daylight_saving_time_changes() {
+        let cet = Timezone::parse_rule("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let sydney = Timezone::parse_rule("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+
+        let local = |year, month, day, hour: i64, minute: i64, second: i64| {
+            days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second
+        };
+
+        let cases = [
+            (&cet, local(2022, 1, 15, 12, 0, 0), 1642244400, 60),
+            (&cet, local(2022, 7, 1, 12, 0, 0), 1656669600, 120),
+            (&cet, local(2022, 3, 27, 1, 59, 59), 1648342799, 60),
+            (&cet, local(2022, 3, 27, 3, 0, 0), 1648342800, 120),
+            (&cet, local(2022, 10, 30, 1, 59, 59), 1667087999, 120),
+            (&cet, local(2022, 10, 30, 3, 0, 0), 1667095200, 60),
+            // 02:30 does not exist on the day clocks go forward, and is taken
+            // as 03:30 CEST.
+            (&cet, local(2022, 3, 27, 2, 30, 0), 1648344600, 120),
+            // 02:30 happens twice on the day clocks go back, and the second
+            // one (CET) is taken.
+            (&cet, local(2022, 10, 30, 2, 30, 0), 1667093400, 60),
+            (&sydney, local(2022, 4, 3, 1, 59, 59), 1648911599, 660),
+            (&sydney, local(2022, 10, 2, 3, 0, 0), 1664640000, 660),
+            (&sydney, local(2022, 10, 2, 1, 59, 59), 1664639999, 600),
+            (&sydney, local(2022, 7, 1, 12, 0, 0), 1656640800, 600),
+        ];
+
+        for (timezone, local, timestamp, offset_minutes) in cases {
+            assert_eq!(
+                Date::local_time_in(local, timezone),
+                Date {
+                    timestamp,
+                    offset_minutes
+                },
+                "{}",
+                local
+            );
+        }
+    }
+
+    #[test]
+    fn formats_extreme_timestamps_without_overflowing() {
+        for timestamp in [i64::MAX, i64::MIN] {
+            let date = Date {
+                timestamp,
+                offset_minutes: if timestamp > 0 { 14 * 60 } else { -14 * 60 },
+            };
+
+            date.format(&DateFormat::Default);
+            date.format_relative(0);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+// Line based diffing using Myers' O(ND) algorithm, in its linear space variant.
+// See "An O(ND) Difference Algorithm and Its Variations" (Eugene W. Myers, 1986).
+//
+// Instead of remembering every step of the search to walk the path back, both
+// ends are searched at the same time until they meet on a "middle snake" of a
+// shortest edit script, and the parts before and after it are diffed the same
+// way. This takes O(N+M) memory instead of O((N+M)D), which for large files
+// with many differences would not fit in memory.
+
+// Returns the start and end (as (x, y) positions) of the middle snake of a
+// shortest edit script turning a into b: the diagonal run where the searches
+// from both ends meet. Neither a nor b can be empty.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> ((usize, usize), (usize, usize)) {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+
+    // forward[k + offset] holds the furthest x reached on diagonal k from the
+    // start, backward[k + offset] the furthest x reached on diagonal k from the
+    // end, counting from the end (diagonal k from the end is delta - k from the
+    // start).
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+
+    let snake = |x0: isize, y0: isize, x1: isize, y1: isize| {
+        ((x0 as usize, y0 as usize), (x1 as usize, y1 as usize))
+    };
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+
+            let mut x = if k == -d || (k != d && forward[index - 1] < forward[index + 1]) {
+                forward[index + 1]
+            } else {
+                forward[index - 1] + 1
+            };
+            let mut y = x - k;
+            let (start_x, start_y) = (x, y);
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            forward[index] = x;
+
+            // The backward search has done d - 1 steps.
+            let backward_k = delta - k;
+
+            if odd && backward_k.abs() < d && x + backward[(backward_k + offset) as usize] >= n {
+                return snake(start_x, start_y, x, y);
+            }
+        }
+
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+
+            let mut x = if k == -d || (k != d && backward[index - 1] < backward[index + 1]) {
+                backward[index + 1]
+            } else {
+                backward[index - 1] + 1
+            };
+            let mut y = x - k;
+            let (start_x, start_y) = (x, y);
+
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            backward[index] = x;
+
+            let forward_k = delta - k;
+
+            if !odd && forward_k.abs() <= d && x + forward[(forward_k + offset) as usize] >= n {
+                return snake(n - x, m - y, n - start_x, m - start_y);
+            }
+        }
+    }
+
+    unreachable!("the searches from both ends always meet")
+}
+
+// Adds the matching pairs of a[a_start..] and b[b_start..] (given as slices),
+// in increasing order.
+fn find_matches<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    a_start: usize,
+    b_start: usize,
+    matches: &mut Vec<(usize, usize)>,
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    matches.extend((0..prefix).map(|i| (a_start + i, b_start + i)));
+
+    let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+
+    // With the common ends trimmed, the middle snake is neither at the start
+    // nor at the end, so both halves are smaller than the whole.
+    if !a.is_empty() && !b.is_empty() {
+        let ((x, y), (u, v)) = middle_snake(a, b);
+
+        find_matches(&a[..x], &b[..y], a_start, b_start, matches);
+        matches.extend((0..u - x).map(|i| (a_start + x + i, b_start + y + i)));
+        find_matches(&a[u..], &b[v..], a_start + u, b_start + v, matches);
+    }
+
+    matches.extend((0..suffix).map(|i| (a_start + a.len() + i, b_start + b.len() + i)));
+}
+
+// Returns the pairs of indices (in a and in b) of the elements that are kept
+// unchanged by a shortest edit script turning a into b. Pairs are returned in
+// increasing order for both indices.
+pub fn matching_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let mut matches = vec![];
+
+    find_matches(a, b, 0, 0, &mut matches);
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    use super::*;
+
+    // Counts the bytes allocated by each thread, to check how much memory
+    // diffing takes.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+        static PEAK: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATED.try_with(|allocated| {
+                allocated.set(allocated.get() + layout.size());
+                let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+            });
+
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = ALLOCATED
+                .try_with(|allocated| allocated.set(allocated.get().saturating_sub(layout.size())));
+
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    // Length of the longest common subsequence, by dynamic programming.
+    fn lcs_length(a: &[u8], b: &[u8]) -> usize {
+        let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                lengths[i + 1][j + 1] = if a[i] == b[j] {
+                    lengths[i][j] + 1
+                } else {
+                    lengths[i][j + 1].max(lengths[i + 1][j])
+                };
+            }
+        }
+
+        lengths[a.len()][b.len()]
+    }
+
+    #[test]
+    fn matches_examples() {
+        assert_eq!(matching_lines::<u8>(&[], &[]), []);
+        assert_eq!(matching_lines(b"abc", b""), []);
+        assert_eq!(matching_lines(b"", b"abc"), []);
+        assert_eq!(matching_lines(b"abc", b"abc"), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(matching_lines(b"abc", b"xyz"), []);
+        assert_eq!(matching_lines(b"abc", b"axc"), [(0, 0), (2, 2)]);
+        assert_eq!(matching_lines(b"abcabba", b"cbabac").len(), 4);
+    }
+
+    #[test]
+    fn matches_are_a_longest_common_subsequence() {
+        // A small linear congruential generator, for reproducible inputs.
+        let mut seed = 12345u32;
+        let mut random = |bound: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % bound
+        };
+
+        for _ in 0..500 {
+            let a: Vec<u8> = (0..random(20)).map(|_| b'a' + random(4) as u8).collect();
+            let b: Vec<u8> = (0..random(20)).map(|_| b'a' + random(4) as u8).collect();
+
+            let matches = matching_lines(&a, &b);
+
+            assert_eq!(matches.len(), lcs_length(&a, &b), "{:?} {:?}", a, b);
+            assert!(matches.iter().all(|&(i, j)| a[i] == b[j]));
+            assert!(matches
+                .windows(2)
+                .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1));
+        }
+    }
+
+    #[test]
+    fn memory_is_linear_in_the_input() {
+        // Unrelated inputs, the worst case: D is N + M.
+        let a: Vec<usize> = (0..3000).collect();
+        let b: Vec<usize> = (3000..6000).collect();
+
+        let before = ALLOCATED.with(Cell::get);
+        PEAK.with(|peak| peak.set(before));
+
+        assert_eq!(matching_lines(&a, &b), []);
+
+        // Keeping every step of the search would take (N + M) * D words, over
+        // 250MB here.
+        let used = PEAK.with(Cell::get) - before;
+        assert!(used < 1 << 20, "{} bytes used", used);
+    }
+}
@@ -0,0 +1,375 @@
+use std::{cmp::Ordering, iter::Peekable, slice::Iter};
+
+use super::{
+    object::{
+        oid::Oid,
+        tree::{FileMode, Tree, TreeEntry},
+    },
+    pathspec::Pathspec,
+    repository::Repository,
+};
+
+// Comparing two trees entry by entry, as `git diff-tree` does. Entries are
+// compared by mode and object id only: subtrees with the same id are skipped
+// without being read, and blobs are never read. Log path filtering, pickaxe
+// and merges build on the list of changed files this gives.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaStatus {
+    Added,
+    Deleted,
+    // Same kind of entry with other contents or mode (like a file becoming
+    // executable).
+    Modified,
+    // A file becoming a symbolic link or a submodule, or the other way around.
+    TypeChanged,
+}
+
+impl DeltaStatus {
+    // Returns the letter git uses for the status in its raw output.
+    pub fn as_char(&self) -> char {
+        match self {
+            DeltaStatus::Added => 'A',
+            DeltaStatus::Deleted => 'D',
+            DeltaStatus::Modified => 'M',
+            DeltaStatus::TypeChanged => 'T',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDelta {
+    pub status: DeltaStatus,
+    pub path: String,
+    // Mode and object id on each side, None where the entry does not exist.
+    pub old: Option<(FileMode, Oid)>,
+    pub new: Option<(FileMode, Oid)>,
+}
+
+#[derive(Default)]
+pub struct DiffTreeOptions<'a> {
+    // Descend into subtrees, reporting the files that changed in them instead
+    // of the subtrees themselves.
+    pub recursive: bool,
+    // Only report changes to paths it matches.
+    pub pathspec: Option<&'a Pathspec>,
+}
+
+fn read_tree(repo: &Repository, sha: Option<&str>) -> Result<Vec<TreeEntry>, String> {
+    let sha = match sha {
+        Some(sha) => sha,
+        None => return Ok(vec![]),
+    };
+
+    let (object_type, content) = repo.object_read_raw(sha)?;
+
+    if object_type != "tree" {
+        return Err(format!("Object {} is a {}, not a tree", sha, object_type));
+    }
+
+    Ok(Tree::parse(&content)?.entries().to_vec())
+}
+
+// Returns the next entries to compare: one from each tree if they have the
+// same name and kind, otherwise the one sorting first on its own.
+fn next_pair<'a>(
+    old: &mut Peekable<Iter<'a, TreeEntry>>,
+    new: &mut Peekable<Iter<'a, TreeEntry>>,
+) -> Option<(Option<&'a TreeEntry>, Option<&'a TreeEntry>)> {
+    let ordering = match (old.peek(), new.peek()) {
+        (None, None) => return None,
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(old_entry), Some(new_entry)) => old_entry.sort_key().cmp(&new_entry.sort_key()),
+    };
+
+    Some(match ordering {
+        Ordering::Less => (old.next(), None),
+        Ordering::Greater => (None, new.next()),
+        Ordering::Equal => (old.next(), new.next()),
+    })
+}
+
+fn status(old: Option<&TreeEntry>, new: Option<&TreeEntry>) -> DeltaStatus {
+    let kind = |mode: FileMode| match mode {
+        FileMode::Blob | FileMode::Executable => FileMode::Blob,
+        mode => mode,
+    };
+
+    match (old, new) {
+        (None, _) => DeltaStatus::Added,
+        (_, None) => DeltaStatus::Deleted,
+        (Some(old), Some(new)) if kind(old.mode) != kind(new.mode) => DeltaStatus::TypeChanged,
+        _ => DeltaStatus::Modified,
+    }
+}
+
+fn diff_trees(
+    repo: &Repository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    prefix: &str,
+    options: &DiffTreeOptions,
+    deltas: &mut Vec<FileDelta>,
+) -> Result<(), String> {
+    let old_entries = read_tree(repo, old_tree)?;
+    let new_entries = read_tree(repo, new_tree)?;
+
+    let mut old_iter = old_entries.iter().peekable();
+    let mut new_iter = new_entries.iter().peekable();
+
+    while let Some((old, new)) = next_pair(&mut old_iter, &mut new_iter) {
+        if let (Some(old), Some(new)) = (old, new) {
+            if old.mode == new.mode && old.oid == new.oid {
+                continue;
+            }
+        }
+
+        let name = &old.or(new).unwrap().name;
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        // Entries are only paired when both are trees or neither is. Subtrees
+        // the pathspec cannot select anything in are skipped without being
+        // read. When not recursing, the others are reported, so "dir/file"
+        // reports "dir" like in git.
+        let is_tree = old.or(new).unwrap().mode == FileMode::Tree;
+        let selected = match options.pathspec {
+            Some(pathspec) if is_tree => pathspec.may_match_in(&path),
+            Some(pathspec) => pathspec.matches(&path),
+            None => true,
+        };
+
+        if !selected {
+            continue;
+        }
+
+        if options.recursive && is_tree {
+            diff_trees(
+                repo,
+                old.map(|entry| entry.oid.to_hex()).as_deref(),
+                new.map(|entry| entry.oid.to_hex()).as_deref(),
+                &path,
+                options,
+                deltas,
+            )?;
+
+            continue;
+        }
+
+        deltas.push(FileDelta {
+            status: status(old, new),
+            path,
+            old: old.map(|entry| (entry.mode, entry.oid)),
+            new: new.map(|entry| (entry.mode, entry.oid)),
+        });
+    }
+
+    Ok(())
+}
+
+// Returns the changes between two trees, given by id, in the order git lists
+// them. A missing tree (like the parent of a root commit) has no entries.
+pub fn diff_tree_to_tree(
+    repo: &Repository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    options: &DiffTreeOptions,
+) -> Result<Vec<FileDelta>, String> {
+    let mut deltas = vec![];
+
+    diff_trees(repo, old_tree, new_tree, "", options, &mut deltas)?;
+
+    Ok(deltas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{object::blob::Blob, testing::TestRepository};
+
+    impl TestRepository {
+        fn diff_result(
+            &self,
+            old: Option<&str>,
+            new: Option<&str>,
+            recursive: bool,
+            pathspec: &[&str],
+        ) -> Result<Vec<(char, String)>, String> {
+            let pathspec: Vec<String> = pathspec.iter().map(|spec| spec.to_string()).collect();
+            let pathspec = Pathspec::parse(&pathspec)?;
+            let options = DiffTreeOptions {
+                recursive,
+                pathspec: Some(&pathspec),
+            };
+
+            Ok(diff_tree_to_tree(&self.repo, old, new, &options)?
+                .into_iter()
+                .map(|delta| (delta.status.as_char(), delta.path))
+                .collect())
+        }
+
+        fn diff(
+            &self,
+            old: Option<&str>,
+            new: Option<&str>,
+            recursive: bool,
+            pathspec: &[&str],
+        ) -> Vec<(char, String)> {
+            self.diff_result(old, new, recursive, pathspec).unwrap()
+        }
+    }
+
+    fn deltas(expected: &[(char, &str)]) -> Vec<(char, String)> {
+        expected
+            .iter()
+            .map(|(status, path)| (*status, path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn statuses() {
+        let test = TestRepository::new("statuses");
+        let old = test.tree(&[
+            ("deleted", FileMode::Blob, "a"),
+            ("link", FileMode::Blob, "target"),
+            ("modified", FileMode::Blob, "a"),
+            ("same", FileMode::Blob, "a"),
+            ("script", FileMode::Blob, "a"),
+        ]);
+        let new = test.tree(&[
+            ("added", FileMode::Blob, "a"),
+            ("link", FileMode::Symlink, "target"),
+            ("modified", FileMode::Blob, "b"),
+            ("same", FileMode::Blob, "a"),
+            ("script", FileMode::Executable, "a"),
+        ]);
+
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), false, &[]),
+            deltas(&[
+                ('A', "added"),
+                ('D', "deleted"),
+                ('T', "link"),
+                ('M', "modified"),
+                ('M', "script"),
+            ])
+        );
+    }
+
+    #[test]
+    fn root_commit() {
+        let test = TestRepository::new("root_commit");
+        let tree = test.tree(&[
+            ("dir/file", FileMode::Blob, "a"),
+            ("file", FileMode::Blob, "a"),
+        ]);
+
+        assert_eq!(
+            test.diff(None, Some(&tree), true, &[]),
+            deltas(&[('A', "dir/file"), ('A', "file")])
+        );
+        assert_eq!(
+            test.diff(Some(&tree), None, false, &[]),
+            deltas(&[('D', "dir"), ('D', "file")])
+        );
+    }
+
+    #[test]
+    fn file_replaced_by_directory() {
+        let test = TestRepository::new("file_replaced_by_directory");
+        let old = test.tree(&[("x", FileMode::Blob, "a")]);
+        let new = test.tree(&[("x/y", FileMode::Blob, "a")]);
+
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), true, &[]),
+            deltas(&[('D', "x"), ('A', "x/y")])
+        );
+        assert_eq!(
+            test.diff(Some(&new), Some(&old), false, &[]),
+            deltas(&[('A', "x"), ('D', "x")])
+        );
+    }
+
+    #[test]
+    fn pathspecs() {
+        let test = TestRepository::new("pathspecs");
+        let old = test.tree(&[
+            ("dir/file", FileMode::Blob, "a"),
+            ("dir/other", FileMode::Blob, "a"),
+            ("src/main.rs", FileMode::Blob, "a"),
+        ]);
+        let new = test.tree(&[
+            ("dir/file", FileMode::Blob, "a"),
+            ("dir/other", FileMode::Blob, "b"),
+            ("src/main.rs", FileMode::Blob, "b"),
+        ]);
+
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), false, &["dir/file"]),
+            deltas(&[('M', "dir")])
+        );
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), false, &["*.rs"]),
+            deltas(&[('M', "dir"), ('M', "src")])
+        );
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), true, &["dir/file"]),
+            deltas(&[])
+        );
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), true, &["*.rs"]),
+            deltas(&[('M', "src/main.rs")])
+        );
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), true, &[":!dir"]),
+            deltas(&[('M', "src/main.rs")])
+        );
+    }
+
+    #[test]
+    fn unselected_subtrees_are_not_read() {
+        let test = TestRepository::new("unselected_subtrees_are_not_read");
+        let blob = Oid::from_hex(&test.repo.object_write(&Blob::new(vec![]), true)).unwrap();
+        let entry = |mode, name: &str, oid| TreeEntry {
+            mode,
+            name: name.to_owned(),
+            oid,
+        };
+
+        // Both sides have a subtree that does not exist, and cannot be read.
+        let old = test.repo.object_write(
+            &Tree::new(vec![
+                entry(FileMode::Blob, "file", blob),
+                entry(
+                    FileMode::Tree,
+                    "missing",
+                    Oid::from_bytes(&[1; 20]).unwrap(),
+                ),
+            ]),
+            true,
+        );
+        let new = test.repo.object_write(
+            &Tree::new(vec![
+                entry(FileMode::Executable, "file", blob),
+                entry(
+                    FileMode::Tree,
+                    "missing",
+                    Oid::from_bytes(&[2; 20]).unwrap(),
+                ),
+            ]),
+            true,
+        );
+
+        assert_eq!(
+            test.diff(Some(&old), Some(&new), true, &["file"]),
+            deltas(&[('M', "file")])
+        );
+        assert!(test
+            .diff_result(Some(&old), Some(&new), true, &["missing"])
+            .is_err());
+    }
+}
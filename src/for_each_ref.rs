@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+
+use super::{
+    date::{Date, DateFormat},
+    git_object::kvlm_parser,
+    pathspec::Pathspec,
+    repository::Repository,
+};
+
+// Information about a ref that can be shown through format placeholders, used
+// by `got for-each-ref`.
+pub struct RefInfo {
+    pub refname: String,
+    pub objectname: String,
+    pub objecttype: String,
+    pub upstream: Option<String>,
+    pub committerdate: Option<Date>,
+}
+
+pub const DEFAULT_FORMAT: &str = "%(objectname) %(objecttype)\t%(refname)";
+
+// Returns the date of the committer signature of a commit:
+// `committer Hector Lorenzo Pons <hector@hectorlorenzo.me> 1666772992 +0100`
+fn committer_date(content: &str) -> Option<Date> {
+    let kvlm = kvlm_parser(content).ok()?;
+    let (_, committer) = kvlm.iter().find(|(key, _)| *key == "committer")?;
+    let (_, date) = committer.rsplit_once("> ")?;
+
+    Date::parse_raw(date)
+}
+
+// Returns the remote-tracking ref a branch is configured to follow, if any.
+fn upstream(repo: &Repository, refname: &str) -> Option<String> {
+    let branch = refname.strip_prefix("refs/heads/")?;
+    let section = format!("branch \"{}\"", branch);
+    let remote = repo.config_get(&section, "remote")?;
+    let merge = repo.config_get(&section, "merge")?;
+
+    if remote == "." {
+        return Some(merge);
+    }
+
+    let merge_branch = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+
+    Some(format!("refs/remotes/{}/{}", remote, merge_branch))
+}
+
+fn shorten_refname(refname: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| refname.strip_prefix(prefix))
+        .unwrap_or(refname)
+}
+
+// Collects every ref under refs/ matching the given patterns. Fails if the
+// object of a ref cannot be read, like git does.
+pub fn list_ref_infos(repo: &Repository, patterns: &Pathspec) -> Result<Vec<RefInfo>, String> {
+    let mut infos = vec![];
+
+    for refname in repo.ref_list("refs") {
+        if !patterns.matches(&refname) {
+            continue;
+        }
+
+        let objectname = match repo.ref_read(&refname) {
+            Some(objectname) => objectname,
+            None => continue,
+        };
+        let (objecttype, content) = repo.object_read_raw(&objectname).map_err(|error| {
+            format!(
+                "Could not read object {} for {}: {}",
+                objectname, refname, error
+            )
+        })?;
+
+        let committerdate = if objecttype == "commit" {
+            committer_date(&String::from_utf8_lossy(&content))
+        } else {
+            None
+        };
+
+        infos.push(RefInfo {
+            upstream: upstream(repo, &refname),
+            refname,
+            objectname,
+            objecttype,
+            committerdate,
+        });
+    }
+
+    Ok(infos)
+}
+
+fn trim_leading_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|digit| **digit == b'0').count();
+
+    &digits[zeros..]
+}
+
+// Compares two strings splitting them into runs of digits, compared as
+// numbers, and other characters, compared one by one.
+fn version_compare(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+
+    loop {
+        let a_digits = a.iter().take_while(|byte| byte.is_ascii_digit()).count();
+        let b_digits = b.iter().take_while(|byte| byte.is_ascii_digit()).count();
+
+        let ordering = if a_digits > 0 && b_digits > 0 {
+            let a_number = trim_leading_zeros(&a[..a_digits]);
+            let b_number = trim_leading_zeros(&b[..b_digits]);
+
+            a_number
+                .len()
+                .cmp(&b_number.len())
+                .then_with(|| a_number.cmp(b_number))
+        } else {
+            a.first().cmp(&b.first())
+        };
+
+        if ordering != Ordering::Equal || a.is_empty() {
+            return ordering;
+        }
+
+        a = &a[a_digits.max(1)..];
+        b = &b[b_digits.max(1)..];
+    }
+}
+
+enum SortKey {
+    Refname,
+    // Refnames with embedded numbers compared as numbers, so v1.10 comes after
+    // v1.9.
+    VersionRefname,
+    Objectname,
+    Objecttype,
+    Upstream,
+    Committerdate,
+}
+
+impl SortKey {
+    fn parse(key: &str) -> Result<Self, String> {
+        match key {
+            "refname" => Ok(SortKey::Refname),
+            "version:refname" | "v:refname" => Ok(SortKey::VersionRefname),
+            "objectname" => Ok(SortKey::Objectname),
+            "objecttype" => Ok(SortKey::Objecttype),
+            "upstream" => Ok(SortKey::Upstream),
+            "committerdate" => Ok(SortKey::Committerdate),
+            _ => Err(format!("Unknown sort key: {:?}", key)),
+        }
+    }
+
+    fn compare(&self, a: &RefInfo, b: &RefInfo) -> Ordering {
+        match self {
+            SortKey::Refname => a.refname.cmp(&b.refname),
+            SortKey::VersionRefname => version_compare(&a.refname, &b.refname),
+            SortKey::Objectname => a.objectname.cmp(&b.objectname),
+            SortKey::Objecttype => a.objecttype.cmp(&b.objecttype),
+            SortKey::Upstream => a.upstream.cmp(&b.upstream),
+            SortKey::Committerdate => a
+                .committerdate
+                .map(|date| date.timestamp)
+                .cmp(&b.committerdate.map(|date| date.timestamp)),
+        }
+    }
+}
+
+// Sorts refs by the given keys (prefixed with '-' for descending order). As in
+// git, the last key is the primary one.
+pub fn sort_ref_infos(refs: &mut [RefInfo], keys: &[String]) -> Result<(), String> {
+    for key in keys {
+        let (sort_key, descending) = match key.strip_prefix('-') {
+            Some(key) => (SortKey::parse(key)?, true),
+            None => (SortKey::parse(key)?, false),
+        };
+
+        // Sorting is stable, so sorting by each key in turn leaves the last
+        // one as the primary order.
+        refs.sort_by(|a, b| {
+            let ordering = sort_key.compare(a, b);
+
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn expand_placeholder(info: &RefInfo, placeholder: &str) -> Result<String, String> {
+    let (field, modifier) = match placeholder.split_once(':') {
+        Some((field, modifier)) => (field, Some(modifier)),
+        None => (placeholder, None),
+    };
+
+    let value = match (field, modifier) {
+        ("refname", None) => info.refname.clone(),
+        ("refname", Some("short")) => shorten_refname(&info.refname).to_owned(),
+        ("objectname", None) => info.objectname.clone(),
+        ("objectname", Some("short")) => info.objectname[..7].to_owned(),
+        ("objecttype", None) => info.objecttype.clone(),
+        ("upstream", None) => info.upstream.clone().unwrap_or_default(),
+        ("upstream", Some("short")) => info
+            .upstream
+            .as_deref()
+            .map(shorten_refname)
+            .unwrap_or_default()
+            .to_owned(),
+        ("committerdate", modifier) => {
+            let format = DateFormat::parse(modifier.unwrap_or("default"))?;
+
+            info.committerdate
+                .map(|date| date.format(&format))
+                .unwrap_or_default()
+        }
+        _ => return Err(format!("Unknown field name: %({})", placeholder)),
+    };
+
+    Ok(value)
+}
+
+// Expands a format string such as "%(refname:short) %(objectname)". Besides
+// placeholders, "%%" writes a percent sign and "%xx" the byte with that hex
+// code, which is why the result is bytes and not a string.
+pub fn format_ref_info(info: &RefInfo, format: &str) -> Result<Vec<u8>, String> {
+    let mut output = vec![];
+    let mut rest = format;
+
+    while let Some(index) = rest.find('%') {
+        output.extend_from_slice(&rest.as_bytes()[..index]);
+        rest = &rest[index + 1..];
+
+        if let Some(after) = rest.strip_prefix('%') {
+            output.push(b'%');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('(') {
+            let (placeholder, after) = after
+                .split_once(')')
+                .ok_or_else(|| format!("Unterminated placeholder in format: {:?}", format))?;
+
+            output.extend_from_slice(expand_placeholder(info, placeholder)?.as_bytes());
+            rest = after;
+        } else if let Some(byte) = rest
+            .get(..2)
+            .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            output.push(byte);
+            rest = &rest[2..];
+        } else {
+            output.push(b'%');
+        }
+    }
+
+    output.extend_from_slice(rest.as_bytes());
+
+    Ok(output)
+}
@@ -11,59 +11,82 @@ use sha1::{Digest, Sha1};
 //
 // Remove serialiser mod
 // ```
-fn kvlm_parser<'a>(
-    content: &'a str,
-    kvv: Option<&mut Vec<(&'a str, String)>>,
-) -> Result<Vec<(&'a str, String)>, &'static str> {
-    // we assume we will find a header line, so we look for its key and its value
-    // key will be from beginning to first empty space, value from this point to line break
-    let blank_space_maybe = content.find(' ');
-    let line_break_maybe = content.find('\n');
-
-    if line_break_maybe.is_none() {
-        return Err("Could not find a new line break, content is malformed");
-    }
+//
+// Header values spanning several lines (like gpgsig) continue on lines starting
+// with a space, which is removed from the value. Malformed content is reported
+// with the line it was found on, never panics, and does not recurse, so it is
+// safe to run on untrusted objects of any size.
+pub fn kvlm_parser(content: &str) -> Result<Vec<(&str, String)>, String> {
+    let mut kvlm: Vec<(&str, String)> = vec![];
+    let mut rest = content;
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+
+        let (line, after) = match rest.split_once('\n') {
+            Some(split) => split,
+            // Headers without a message after them.
+            None if rest.is_empty() => {
+                kvlm.push(("", String::new()));
+                return Ok(kvlm);
+            }
+            None => {
+                return Err(format!(
+                    "Line {} is not terminated by a line break",
+                    line_number
+                ))
+            }
+        };
+
+        // A blank line ends the headers, everything after it is the message.
+        if line.is_empty() {
+            kvlm.push(("", after.to_owned()));
+            return Ok(kvlm);
+        }
+
+        if let Some(continuation) = line.strip_prefix(' ') {
+            match kvlm.last_mut() {
+                Some((_, value)) => {
+                    value.push('\n');
+                    value.push_str(continuation);
+                }
+                None => {
+                    return Err(format!(
+                        "Line {} continues a header, but there is none before it",
+                        line_number
+                    ))
+                }
+            }
+        } else {
+            match line.split_once(' ') {
+                Some((key, value)) => kvlm.push((key, value.to_owned())),
+                None => {
+                    return Err(format!(
+                        "Header on line {} has no value: {:?}",
+                        line_number, line
+                    ))
+                }
+            }
+        }
 
-    let line_break = line_break_maybe.unwrap();
-
-    // kvv is an optional argument because we only pass it when doing recursion.
-    // Because it is optional, we need to create a vector if None has been passed
-    // (when method is called on the first recursion).
-    let mut temp_kvv = match kvv {
-        Some(k) => k.to_owned(),
-        None => vec![],
-    };
-
-    // if there is no blank space, it means that we have reached a blank line,
-    // and we can start storing the message. If there is a blank space, we have
-    // a header line.
-    if blank_space_maybe.is_none() || (blank_space_maybe.unwrap() > line_break) {
-        temp_kvv.push(("", (&content[line_break + 1..]).to_owned()));
-        return Ok(temp_kvv);
-    } else {
-        let blank_space = blank_space_maybe.unwrap();
-
-        temp_kvv.push((
-            &content[..blank_space],
-            (&content[blank_space + 1..line_break]).to_owned(),
-        ));
-        return kvlm_parser(&content[line_break + 1..], Some(&mut temp_kvv));
+        rest = after;
     }
 }
 
-fn kvlm_serialize(kvv: &Vec<(&str, String)>) -> String {
-    return kvv.iter().fold(String::from(""), |acc, current| {
+pub fn kvlm_serialize(kvv: &Vec<(&str, String)>) -> String {
+    kvv.iter().fold(String::from(""), |acc, current| {
         let key = current.0;
         let value = &current.1;
 
         // if key is an empty string, it means it is the content, and we should format
         // it differently (do not show they key, add a line break before it).
-        if key == "" {
+        if key.is_empty() {
             acc + format!("\n{}", value).as_str()
         } else {
-            acc + format!("{} {}\n", key, value).as_str()
+            acc + format!("{} {}\n", key, value.replace('\n', "\n ")).as_str()
         }
-    });
+    })
 }
 
 pub enum GitObject {
@@ -0,0 +1,102 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_to_string,
+};
+
+use super::{object::commit::Commit, repository::Repository, validate::validate_object_id};
+
+// Overrides of the parents recorded in commits, used when walking history:
+//
+// - .got/info/grafts has one `<commit> [<parent>...]` line per rewritten
+//   commit, replacing its parents with the listed ones.
+// - .got/shallow lists the boundary commits of a shallow repository, whose
+//   parents were not fetched. They are walked as if they had no parents.
+//
+// Commits whose parents were substituted may point to parents missing from the
+// repository, so anything checking connectivity should ask is_grafted first.
+pub struct Grafts {
+    parents: HashMap<String, Vec<String>>,
+}
+
+// Reads `<commit> [<parent>...]` lines, skipping comments and blank lines.
+// Lines with invalid object ids are ignored rather than failing the walk.
+fn read_graft_lines(content: &str, grafts: &mut HashMap<String, Vec<String>>) {
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut ids = line.split_whitespace().map(str::to_owned);
+        let commit = ids.next().unwrap();
+        let parents = ids.collect::<Vec<_>>();
+
+        if std::iter::once(&commit)
+            .chain(&parents)
+            .all(|id| validate_object_id(id).is_ok())
+        {
+            grafts.insert(commit, parents);
+        }
+    }
+}
+
+impl Grafts {
+    pub fn load(repo: &Repository) -> Self {
+        let mut parents = HashMap::new();
+
+        if let Ok(content) = read_to_string(repo.repo_path("info/grafts")) {
+            read_graft_lines(&content, &mut parents);
+        }
+
+        // A shallow line is a graft with no parents. It goes last, as the
+        // shallow boundary wins over a graft of the same commit.
+        if let Ok(content) = read_to_string(repo.repo_path("shallow")) {
+            for line in content.lines().map(str::trim) {
+                if validate_object_id(line).is_ok() {
+                    parents.insert(line.to_owned(), vec![]);
+                }
+            }
+        }
+
+        Grafts { parents }
+    }
+
+    pub fn is_grafted(&self, sha: &str) -> bool {
+        self.parents.contains_key(sha)
+    }
+
+    // Returns the parents of a commit, as substituted by a graft or the shallow
+    // boundary if there is one, otherwise as recorded in the commit.
+    pub fn commit_parents(&self, repo: &Repository, sha: &str) -> Vec<String> {
+        if let Some(parents) = self.parents.get(sha) {
+            return parents.clone();
+        }
+
+        match repo.object_read_raw(sha) {
+            Ok((object_type, content)) if object_type == "commit" => {
+                Commit::new(String::from_utf8_lossy(&content).into_owned()).parents()
+            }
+            _ => vec![],
+        }
+    }
+
+    // Returns true if ancestor can be reached from descendant by following
+    // parents (a commit being its own ancestor).
+    pub fn is_ancestor(&self, repo: &Repository, ancestor: &str, descendant: &str) -> bool {
+        let mut pending = vec![descendant.to_owned()];
+        let mut seen = HashSet::new();
+
+        while let Some(sha) = pending.pop() {
+            if sha == ancestor {
+                return true;
+            }
+
+            if seen.insert(sha.clone()) {
+                pending.extend(self.commit_parents(repo, &sha));
+            }
+        }
+
+        false
+    }
+}
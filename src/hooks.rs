@@ -0,0 +1,118 @@
+use std::{
+    io,
+    path::PathBuf,
+    process::{Command, ExitStatus, Stdio},
+    sync::PoisonError,
+};
+
+use super::{platform::is_executable, repository::Repository};
+
+// Things that happen to HEAD and the worktree, for whoever needs to react to
+// them (environment managers, build caches...). Tools using the library
+// register a listener on the repository, which the library notifies, and
+// anything else can use hooks: programs in .got/hooks (or core.hooksPath)
+// named after the event, which commands run as git runs them. Hooks that do
+// not exist or are not executable are skipped.
+
+// Object id given to hooks for a commit that does not exist, like the one of
+// an unborn HEAD.
+pub const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+pub type Listener = Box<dyn Fn(&Event) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    // The worktree was updated from a commit. HEAD moved from old to new if
+    // switching branches, and stayed where it was if only files were restored.
+    Checkout {
+        old: String,
+        new: String,
+        branch_switch: bool,
+    },
+    // A merge updated HEAD and the worktree.
+    Merge {
+        squash: bool,
+    },
+}
+
+impl Event {
+    // The hook run for the event, and its arguments.
+    fn hook(&self) -> (&'static str, Vec<&str>) {
+        let flag = |value: bool| if value { "1" } else { "0" };
+
+        match self {
+            Event::Checkout {
+                old,
+                new,
+                branch_switch,
+            } => ("post-checkout", vec![old, new, flag(*branch_switch)]),
+            Event::Merge { squash } => ("post-merge", vec![flag(*squash)]),
+        }
+    }
+}
+
+fn hook_path(repo: &Repository, name: &str) -> Option<PathBuf> {
+    // Relative hook paths are relative to the worktree, where hooks run.
+    let directory = match repo.config_get("core", "hooksPath") {
+        Some(path) => repo.worktree().join(path),
+        None => repo.repo_path("hooks"),
+    };
+
+    Some(directory.join(name)).filter(|path| is_executable(path))
+}
+
+// Runs a hook from the root of the worktree, with its output going to stderr
+// like git does, so it does not mix with the output of commands. Returns None
+// if there is no such hook.
+pub fn run_hook(repo: &Repository, name: &str, args: &[&str]) -> io::Result<Option<ExitStatus>> {
+    let path = match hook_path(repo, name) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    Command::new(path)
+        .args(args)
+        .current_dir(repo.worktree())
+        .stdin(Stdio::null())
+        .stdout(io::stderr())
+        .status()
+        .map(Some)
+}
+
+// Runs the hook of an event. Commands run it once they are done and have
+// reported what they did, and report a failing hook on its own, as the change
+// it follows cannot be undone.
+pub fn run_event_hook(repo: &Repository, event: &Event) -> Result<(), String> {
+    let (name, args) = event.hook();
+
+    match run_hook(repo, name, &args) {
+        Ok(Some(status)) if !status.success() => Err(format!("{} hook failed ({})", name, status)),
+        Ok(_) => Ok(()),
+        Err(error) => Err(format!("Could not run {} hook: {}", name, error)),
+    }
+}
+
+impl Repository {
+    // Registers a function called with every event happening to the
+    // repository. Listeners cannot register other listeners.
+    pub fn on_event(&self, listener: impl Fn(&Event) + Send + Sync + 'static) {
+        self.listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+
+    // Tells the listeners about an event, once the change is done. A listener
+    // that panicked before leaves the list as it was, so the others are still
+    // called.
+    pub fn notify(&self, event: &Event) {
+        let listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        for listener in listeners.iter() {
+            listener(event);
+        }
+    }
+}
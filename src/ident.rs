@@ -0,0 +1,157 @@
+use std::{env, fmt};
+
+use super::{date::Date, repository::Repository};
+
+// Resolution of the identities and editor used by commands, so that every
+// command (and scripts, through `got var`) agree on who is committing.
+//
+// Values are looked up in the environment first, then in the repository
+// configuration, and finally fall back to what we can guess from the system.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Committer,
+}
+
+impl Role {
+    fn env_prefix(&self) -> &'static str {
+        match self {
+            Role::Author => "GOT_AUTHOR",
+            Role::Committer => "GOT_COMMITTER",
+        }
+    }
+}
+
+// Who did something and when, as written in commit and tag headers:
+// `Hector Lorenzo Pons <hector@hectorlorenzo.me> 1666772992 +0100`
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub date: Date,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {}",
+            self.name,
+            self.email,
+            self.date.format_raw()
+        )
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn config_var(repo: Option<&Repository>, section: &str, key: &str) -> Option<String> {
+    repo.and_then(|repo| repo.config_get(section, key))
+        .filter(|value| !value.is_empty())
+}
+
+fn system_username() -> String {
+    env_var("USER")
+        .or_else(|| env_var("USERNAME"))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+fn system_hostname() -> String {
+    env_var("HOSTNAME")
+        .or_else(|| env_var("COMPUTERNAME"))
+        .unwrap_or_else(|| String::from("localhost"))
+}
+
+// The date is the current time in the local timezone, unless overridden with
+// GOT_AUTHOR_DATE or GOT_COMMITTER_DATE (which makes objects reproducible).
+// Those accept anything Date::parse does, like `1666772992 +0100` or
+// `2022-10-26T09:29:52+01:00`, and fail if it cannot be parsed.
+pub fn signature(repo: Option<&Repository>, role: Role) -> Result<Signature, String> {
+    let prefix = role.env_prefix();
+
+    let name = env_var(&format!("{}_NAME", prefix))
+        .or_else(|| config_var(repo, "user", "name"))
+        .unwrap_or_else(system_username);
+
+    let email = env_var(&format!("{}_EMAIL", prefix))
+        .or_else(|| config_var(repo, "user", "email"))
+        .or_else(|| env_var("EMAIL"))
+        .unwrap_or_else(|| format!("{}@{}", system_username(), system_hostname()));
+
+    let date = match env_var(&format!("{}_DATE", prefix)) {
+        Some(date) => Date::parse(&date).ok_or_else(|| format!("invalid date format: {}", date))?,
+        None => Date::now(),
+    };
+
+    Ok(Signature { name, email, date })
+}
+
+pub fn editor(repo: Option<&Repository>) -> String {
+    env_var("GOT_EDITOR")
+        .or_else(|| config_var(repo, "core", "editor"))
+        .or_else(|| env_var("VISUAL"))
+        .or_else(|| env_var("EDITOR"))
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The only test reading GOT_AUTHOR_DATE and GOT_COMMITTER_DATE, so
+    // setting them does not race with other tests.
+    #[test]
+    fn dates_can_be_overridden() {
+        let date = |role: Role| signature(None, role).map(|signature| signature.date);
+
+        env::set_var("GOT_AUTHOR_DATE", "1666772992 +0100");
+        env::set_var("GOT_COMMITTER_DATE", "2022-10-26T09:29:52-05:30");
+
+        assert_eq!(
+            date(Role::Author),
+            Ok(Date {
+                timestamp: 1666772992,
+                offset_minutes: 60
+            })
+        );
+        assert_eq!(
+            date(Role::Committer),
+            Ok(Date {
+                timestamp: 1666796392,
+                offset_minutes: -330
+            })
+        );
+
+        env::set_var("GOT_AUTHOR_DATE", "@1666772992");
+        assert_eq!(
+            date(Role::Author),
+            Ok(Date {
+                timestamp: 1666772992,
+                offset_minutes: 0
+            })
+        );
+
+        for invalid in [
+            "yesterday-ish",
+            "2022-02-30 +0000",
+            "99999999999999999-01-01",
+        ] {
+            env::set_var("GOT_AUTHOR_DATE", invalid);
+            assert_eq!(
+                date(Role::Author),
+                Err(format!("invalid date format: {}", invalid))
+            );
+        }
+
+        // Empty values are ignored, so the date is the current one.
+        env::set_var("GOT_AUTHOR_DATE", "");
+        let before = Date::now().timestamp;
+        let now = date(Role::Author).unwrap().timestamp;
+        assert!(before <= now && now <= Date::now().timestamp);
+
+        env::remove_var("GOT_AUTHOR_DATE");
+        env::remove_var("GOT_COMMITTER_DATE");
+    }
+}
@@ -1,3 +1,24 @@
-fn main() {
-    println!("This is how we do this");
-}
+pub mod checkout;
+pub mod config;
+pub mod date;
+pub mod diff;
+pub mod diff_tree;
+pub mod for_each_ref;
+pub mod git_object;
+pub mod grafts;
+pub mod hooks;
+pub mod ident;
+pub mod merge_file;
+pub mod name_rev;
+pub mod object;
+pub mod pathspec;
+pub mod pktline;
+pub mod platform;
+pub mod refs;
+pub mod repository;
+pub mod server_info;
+pub mod tag;
+#[cfg(test)]
+mod testing;
+pub mod timezone;
+pub mod validate;
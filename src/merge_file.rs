@@ -0,0 +1,298 @@
+use super::diff::matching_lines;
+
+// Three-way merge of file contents, used by `got merge-file`.
+//
+// Both sides are diffed against the base. Lines that are unchanged in both
+// sides split the files into chunks; in each chunk we take whichever side
+// changed, or report a conflict if both sides changed it differently.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MergeFavor {
+    // Write conflict markers.
+    Normal,
+    // Resolve conflicts taking our side.
+    Ours,
+    // Resolve conflicts taking their side.
+    Theirs,
+    // Resolve conflicts taking both sides, ours first.
+    Union,
+}
+
+pub struct MergeFileOptions {
+    pub favor: MergeFavor,
+    pub marker_size: usize,
+    pub ours_label: String,
+    pub theirs_label: String,
+}
+
+impl Default for MergeFileOptions {
+    fn default() -> Self {
+        MergeFileOptions {
+            favor: MergeFavor::Normal,
+            marker_size: 7,
+            ours_label: String::from("ours"),
+            theirs_label: String::from("theirs"),
+        }
+    }
+}
+
+pub struct MergeFileResult {
+    pub content: String,
+    pub conflicts: usize,
+}
+
+fn split_lines(content: &str) -> Vec<&str> {
+    content.split_inclusive('\n').collect()
+}
+
+// Maps every line of base to the line it matches in the other file, if any.
+fn base_matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+
+    for (base_index, other_index) in matching_lines(base, other) {
+        matches[base_index] = Some(other_index);
+    }
+
+    matches
+}
+
+fn push_lines(output: &mut String, lines: &[&str]) {
+    for line in lines {
+        output.push_str(line);
+    }
+}
+
+// Conflict markers must start on their own line, even if the side we just
+// wrote did not end with a line break.
+fn push_marker(output: &mut String, marker: char, size: usize, label: Option<&str>) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output.push_str(&marker.to_string().repeat(size));
+
+    if let Some(label) = label {
+        output.push(' ');
+        output.push_str(label);
+    }
+
+    output.push('\n');
+}
+
+// Writes a chunk that both sides changed, returning the number of conflicts.
+fn push_conflict(
+    output: &mut String,
+    ours: &[&str],
+    theirs: &[&str],
+    options: &MergeFileOptions,
+) -> usize {
+    match options.favor {
+        MergeFavor::Ours => push_lines(output, ours),
+        MergeFavor::Theirs => push_lines(output, theirs),
+        MergeFavor::Union => {
+            push_lines(output, ours);
+            push_lines(output, theirs);
+        }
+        MergeFavor::Normal => {
+            // Lines at the start or end of the chunk that both sides agree on
+            // are kept out of the conflict markers.
+            let prefix = ours
+                .iter()
+                .zip(theirs.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let suffix = ours[prefix..]
+                .iter()
+                .rev()
+                .zip(theirs[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            push_lines(output, &ours[..prefix]);
+            push_marker(output, '<', options.marker_size, Some(&options.ours_label));
+            push_lines(output, &ours[prefix..ours.len() - suffix]);
+            push_marker(output, '=', options.marker_size, None);
+            push_lines(output, &theirs[prefix..theirs.len() - suffix]);
+            push_marker(
+                output,
+                '>',
+                options.marker_size,
+                Some(&options.theirs_label),
+            );
+            push_lines(output, &ours[ours.len() - suffix..]);
+
+            return 1;
+        }
+    }
+
+    0
+}
+
+fn push_chunk(
+    output: &mut String,
+    base: &[&str],
+    ours: &[&str],
+    theirs: &[&str],
+    options: &MergeFileOptions,
+) -> usize {
+    if ours == base || ours == theirs {
+        push_lines(output, theirs);
+    } else if theirs == base {
+        push_lines(output, ours);
+    } else {
+        return push_conflict(output, ours, theirs, options);
+    }
+
+    0
+}
+
+pub fn merge_file(
+    ours: &str,
+    base: &str,
+    theirs: &str,
+    options: &MergeFileOptions,
+) -> MergeFileResult {
+    let ours = split_lines(ours);
+    let base = split_lines(base);
+    let theirs = split_lines(theirs);
+
+    let ours_matches = base_matches(&base, &ours);
+    let theirs_matches = base_matches(&base, &theirs);
+
+    let mut content = String::new();
+    let mut conflicts = 0;
+    let (mut o, mut a, mut b) = (0, 0, 0);
+
+    loop {
+        // Next base line that is kept by both sides.
+        let stable =
+            (o..base.len()).find_map(|index| match (ours_matches[index], theirs_matches[index]) {
+                (Some(a_index), Some(b_index)) => Some((index, a_index, b_index)),
+                _ => None,
+            });
+
+        match stable {
+            Some((o_next, a_next, b_next)) if o_next == o && a_next == a && b_next == b => {
+                content.push_str(base[o]);
+                o += 1;
+                a += 1;
+                b += 1;
+            }
+            Some((o_next, a_next, b_next)) => {
+                conflicts += push_chunk(
+                    &mut content,
+                    &base[o..o_next],
+                    &ours[a..a_next],
+                    &theirs[b..b_next],
+                    options,
+                );
+                o = o_next;
+                a = a_next;
+                b = b_next;
+            }
+            None => {
+                conflicts +=
+                    push_chunk(&mut content, &base[o..], &ours[a..], &theirs[b..], options);
+                break;
+            }
+        }
+    }
+
+    MergeFileResult { content, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "a\nb\nc\nd\ne\n";
+    const OURS: &str = "a\nB\nc\nd\nours\n";
+    const THEIRS: &str = "a\nb\nc\nd\ntheirs\n";
+
+    fn merge(favor: MergeFavor) -> MergeFileResult {
+        let options = MergeFileOptions {
+            favor,
+            ..MergeFileOptions::default()
+        };
+
+        merge_file(OURS, BASE, THEIRS, &options)
+    }
+
+    #[test]
+    fn clean_merge() {
+        let result = merge_file(
+            "a\nB\nc\nd\ne\n",
+            BASE,
+            "a\nb\nc\nd\nE\nf\n",
+            &MergeFileOptions::default(),
+        );
+
+        assert_eq!(result.content, "a\nB\nc\nd\nE\nf\n");
+        assert_eq!(result.conflicts, 0);
+    }
+
+    #[test]
+    fn same_change_on_both_sides_merges_cleanly() {
+        let result = merge_file(OURS, BASE, OURS, &MergeFileOptions::default());
+
+        assert_eq!(result.content, OURS);
+        assert_eq!(result.conflicts, 0);
+    }
+
+    #[test]
+    fn conflict_markers() {
+        let result = merge(MergeFavor::Normal);
+
+        assert_eq!(
+            result.content,
+            "a\nB\nc\nd\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+        );
+        assert_eq!(result.conflicts, 1);
+    }
+
+    #[test]
+    fn conflict_markers_with_labels_and_size() {
+        let options = MergeFileOptions {
+            marker_size: 3,
+            ours_label: String::from("mine"),
+            theirs_label: String::from("yours"),
+            ..MergeFileOptions::default()
+        };
+        let result = merge_file("x", "", "y", &options);
+
+        assert_eq!(result.content, "<<< mine\nx\n===\ny\n>>> yours\n");
+        assert_eq!(result.conflicts, 1);
+    }
+
+    #[test]
+    fn favor_options_resolve_conflicts() {
+        for (favor, expected) in [
+            (MergeFavor::Ours, "a\nB\nc\nd\nours\n"),
+            (MergeFavor::Theirs, "a\nB\nc\nd\ntheirs\n"),
+            (MergeFavor::Union, "a\nB\nc\nd\nours\ntheirs\n"),
+        ] {
+            let result = merge(favor);
+
+            assert_eq!(result.content, expected);
+            assert_eq!(result.conflicts, 0);
+        }
+    }
+
+    #[test]
+    fn large_unrelated_files() {
+        let lines = |prefix: &str| {
+            (0..2000)
+                .map(|index| format!("{} {}\n", prefix, index))
+                .collect::<String>()
+        };
+        let (ours, base, theirs) = (lines("ours"), lines("base"), lines("theirs"));
+
+        let result = merge_file(&ours, &base, &theirs, &MergeFileOptions::default());
+
+        assert_eq!(result.conflicts, 1);
+        assert_eq!(
+            result.content,
+            format!("<<<<<<< ours\n{}=======\n{}>>>>>>> theirs\n", ours, theirs)
+        );
+    }
+}
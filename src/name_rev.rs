@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use super::{
+    date::Date,
+    git_object::kvlm_parser,
+    grafts::Grafts,
+    repository::{Repository, MAX_PEEL_DEPTH},
+};
+
+// Gives commits symbolic names relative to refs, like `master~3` or
+// `tags/v1.0~2^2`, as done by `git name-rev`.
+//
+// Every ref tip is walked down its history. Following a first parent adds one
+// to the generation (`~n`), following the nth parent of a merge starts a new
+// name (`^n`). When a commit can be reached from several tips we keep the
+// name coming from the oldest tag if there is one, and then the closest one.
+
+// Going through a merge weighs much more than following first parents, so
+// names staying on the mainline of a ref are preferred.
+const MERGE_TRAVERSAL_WEIGHT: usize = 65535;
+
+#[derive(Clone)]
+struct RevName {
+    tip_name: String,
+    generation: usize,
+    distance: usize,
+    from_tag: bool,
+    taggerdate: i64,
+}
+
+impl RevName {
+    fn is_better_than(&self, other: &RevName) -> bool {
+        if self.from_tag && other.from_tag && self.taggerdate != other.taggerdate {
+            return self.taggerdate < other.taggerdate;
+        }
+
+        if self.from_tag != other.from_tag {
+            return self.from_tag;
+        }
+
+        self.distance < other.distance
+    }
+
+    fn to_name(&self) -> String {
+        if self.generation == 0 {
+            return self.tip_name.clone();
+        }
+
+        // A dereferenced tag is named `tags/v1^0`, but its ancestors are
+        // `tags/v1~1`, not `tags/v1^0~1`.
+        let tip_name = self.tip_name.strip_suffix("^0").unwrap_or(&self.tip_name);
+
+        format!("{}~{}", tip_name, self.generation)
+    }
+}
+
+fn shorten_refname(refname: &str) -> String {
+    match refname.strip_prefix("refs/heads/") {
+        Some(branch) => branch.to_owned(),
+        None => refname.strip_prefix("refs/").unwrap_or(refname).to_owned(),
+    }
+}
+
+// Returns the timestamp of a signature header (tagger or committer).
+fn signature_timestamp(kvlm: &[(&str, String)], key: &str) -> Option<i64> {
+    let (_, signature) = kvlm.iter().find(|(header, _)| *header == key)?;
+    let (_, date) = signature.rsplit_once("> ")?;
+
+    Date::parse_raw(date).map(|date| date.timestamp)
+}
+
+// Follows annotated tags until reaching a commit, returning its id, whether any
+// tag had to be dereferenced, and the date of the outermost tag (or of the
+// commit itself for lightweight tags). Gives up on tags that loop, which
+// replacements make possible.
+fn peel_to_commit(repo: &Repository, sha: &str) -> Option<(String, bool, i64)> {
+    let mut current = sha.to_owned();
+    let mut dereferenced = false;
+    let mut taggerdate = None;
+
+    for _ in 0..=MAX_PEEL_DEPTH {
+        let (object_type, content) = repo.object_read_raw(&current).ok()?;
+        let content = String::from_utf8_lossy(&content);
+        let kvlm = kvlm_parser(&content).ok()?;
+
+        match object_type.as_str() {
+            "commit" => {
+                let taggerdate = taggerdate.or_else(|| signature_timestamp(&kvlm, "committer"));
+
+                return Some((current, dereferenced, taggerdate.unwrap_or(0)));
+            }
+            "tag" => {
+                let (_, object) = kvlm.iter().find(|(key, _)| *key == "object")?;
+
+                taggerdate = taggerdate.or_else(|| signature_timestamp(&kvlm, "tagger"));
+                current = object.to_owned();
+                dereferenced = true;
+            }
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+fn name_from_tip(
+    repo: &Repository,
+    grafts: &Grafts,
+    names: &mut HashMap<String, RevName>,
+    tip: String,
+    tip_name: RevName,
+) {
+    let mut pending = vec![(tip, tip_name)];
+
+    while let Some((sha, name)) = pending.pop() {
+        if let Some(existing) = names.get(&sha) {
+            if !name.is_better_than(existing) {
+                continue;
+            }
+        }
+
+        names.insert(sha.clone(), name.clone());
+
+        // Pushed in reverse so first parents are walked first.
+        for (index, parent) in grafts
+            .commit_parents(repo, &sha)
+            .into_iter()
+            .enumerate()
+            .rev()
+        {
+            let parent_name = if index == 0 {
+                RevName {
+                    generation: name.generation + 1,
+                    distance: name.distance + 1,
+                    ..name.clone()
+                }
+            } else {
+                // The second parent of `tags/v1^0` is `tags/v1^2`.
+                let tip_name = name.to_name();
+                let tip_name = tip_name.strip_suffix("^0").unwrap_or(&tip_name);
+
+                RevName {
+                    tip_name: format!("{}^{}", tip_name, index + 1),
+                    generation: 0,
+                    distance: name.distance + MERGE_TRAVERSAL_WEIGHT,
+                    from_tag: name.from_tag,
+                    taggerdate: name.taggerdate,
+                }
+            };
+
+            pending.push((parent, parent_name));
+        }
+    }
+}
+
+// Returns the symbolic name of every commit reachable from the refs (only tags
+// if tags_only is set), keyed by commit id.
+pub fn name_revs(repo: &Repository, tags_only: bool) -> HashMap<String, String> {
+    let prefix = if tags_only { "refs/tags" } else { "refs" };
+    let grafts = Grafts::load(repo);
+    let mut names = HashMap::new();
+
+    for refname in repo.ref_list(prefix) {
+        let (tip, dereferenced, taggerdate) = match repo
+            .ref_read(&refname)
+            .and_then(|sha| peel_to_commit(repo, &sha))
+        {
+            Some(peeled) => peeled,
+            None => continue,
+        };
+
+        let mut tip_name = shorten_refname(&refname);
+
+        if dereferenced {
+            tip_name.push_str("^0");
+        }
+
+        let name = RevName {
+            tip_name,
+            generation: 0,
+            distance: 0,
+            from_tag: refname.starts_with("refs/tags/"),
+            taggerdate,
+        };
+
+        name_from_tip(repo, &grafts, &mut names, tip, name);
+    }
+
+    names
+        .into_iter()
+        .map(|(sha, name)| (sha, name.to_name()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        object::{tag::Tag, tree::FileMode},
+        testing::TestRepository,
+    };
+
+    #[test]
+    fn names_parents_of_a_tagged_merge() {
+        let test = TestRepository::new("names_parents_of_a_tagged_merge");
+        let root = test.commit(&[("f", FileMode::Blob, "root")], &[]);
+        let side = test.commit(&[("f", FileMode::Blob, "side")], &[&root]);
+        let merge = test.commit(&[("f", FileMode::Blob, "merge")], &[&root, &side]);
+        let tag = test.repo.object_write(
+            &Tag::new(format!(
+                "object {}\ntype commit\ntag v1\ntagger A U Thor <author@example.com> 1666772992 +0100\n\nv1\n",
+                merge
+            )),
+            true,
+        );
+
+        test.repo.ref_write("refs/tags/v1", &tag).unwrap();
+
+        let names = name_revs(&test.repo, true);
+
+        assert_eq!(names[&merge], "tags/v1^0");
+        assert_eq!(names[&root], "tags/v1~1");
+        assert_eq!(names[&side], "tags/v1^2");
+    }
+
+    #[test]
+    fn names_parents_of_a_merge_on_a_branch() {
+        let test = TestRepository::new("names_parents_of_a_merge_on_a_branch");
+        let root = test.commit(&[("f", FileMode::Blob, "root")], &[]);
+        let side = test.commit(&[("f", FileMode::Blob, "side")], &[&root]);
+        let merge = test.commit(&[("f", FileMode::Blob, "merge")], &[&root, &side]);
+        let tip = test.commit(&[("f", FileMode::Blob, "tip")], &[&merge]);
+
+        test.repo.ref_write("refs/heads/main", &tip).unwrap();
+
+        let names = name_revs(&test.repo, false);
+
+        assert_eq!(names[&tip], "main");
+        assert_eq!(names[&merge], "main~1");
+        assert_eq!(names[&root], "main~2");
+        assert_eq!(names[&side], "main~1^2");
+    }
+}
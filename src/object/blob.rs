@@ -2,23 +2,24 @@ use sha1::{Digest, Sha1};
 
 use super::serialise::Serialise;
 
+// File contents are arbitrary bytes, which need not be valid UTF-8.
 pub struct Blob {
-    content: String,
+    content: Vec<u8>,
 }
 
 impl Blob {
-    pub fn new(content: String) -> Self {
+    pub fn new(content: Vec<u8>) -> Self {
         Blob { content }
     }
 }
 
 impl Serialise for Blob {
-    fn serialise(&self) -> &String {
-        &self.content
+    fn serialise(&self) -> Vec<u8> {
+        self.content.clone()
     }
 
-    fn content_with_headers(&self) -> String {
-        format!("{}{}", self.encoded_header(), self.serialise())
+    fn content_with_headers(&self) -> Vec<u8> {
+        [self.encoded_header().into_bytes(), self.serialise()].concat()
     }
 
     fn encoded_header(&self) -> String {
@@ -1,24 +1,84 @@
 use sha1::{Digest, Sha1};
 
-use super::serialise::Serialise;
+use super::{oid::Oid, serialise::Serialise, validate_signature};
+use crate::git_object::kvlm_parser;
 
 pub struct Commit {
     content: String,
 }
 
+impl Commit {
+    pub fn new(content: String) -> Self {
+        Commit { content }
+    }
+
+    // Validates the raw contents of a commit object: a tree header, any number
+    // of parent headers, author and committer headers, and then optional
+    // headers (encoding, gpgsig...) before the message.
+    pub fn validate(content: &str) -> Result<(), String> {
+        let kvlm = kvlm_parser(content)?;
+        let mut headers = kvlm.iter().peekable();
+
+        match headers.next() {
+            Some(("tree", tree)) => {
+                Oid::from_hex(tree)
+                    .map_err(|error| format!("Invalid tree {:?}: {}", tree, error))?;
+            }
+            _ => return Err(String::from("Commit must start with a tree header")),
+        }
+
+        while let Some((_, parent)) = headers.next_if(|(key, _)| *key == "parent") {
+            Oid::from_hex(parent)
+                .map_err(|error| format!("Invalid parent {:?}: {}", parent, error))?;
+        }
+
+        for expected in ["author", "committer"] {
+            match headers.next() {
+                Some((key, signature)) if *key == expected => {
+                    validate_signature(expected, signature)?
+                }
+                _ => return Err(format!("Commit is missing its {} header", expected)),
+            }
+        }
+
+        if headers.any(|(key, _)| ["tree", "parent", "author", "committer"].contains(key)) {
+            return Err(String::from(
+                "Commit has tree, parent, author or committer headers out of place",
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Returns the values of every header with the given key, in order.
+    pub fn headers(&self, key: &str) -> Vec<String> {
+        kvlm_parser(&self.content)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(header, _)| *header == key)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    // Commits have no parents (root commits), one, or several (merges).
+    pub fn parents(&self) -> Vec<String> {
+        self.headers("parent")
+    }
+}
+
 impl Serialise for Commit {
-    fn serialise(&self) -> &String {
-        &self.content
+    fn serialise(&self) -> Vec<u8> {
+        self.content.as_bytes().to_vec()
     }
 
-    fn content_with_headers(&self) -> String {
-        format!("{}{}", self.encoded_header(), self.serialise())
+    fn content_with_headers(&self) -> Vec<u8> {
+        [self.encoded_header().into_bytes(), self.serialise()].concat()
     }
 
     fn encoded_header(&self) -> String {
         let content = self.serialise();
 
-        format!("{} {}\x00", "blob", content.len())
+        format!("{} {}\x00", "commit", content.len())
     }
 
     fn hash(&self) -> String {
@@ -1,5 +1,329 @@
 pub mod blob;
 pub mod commit;
+pub mod oid;
 pub mod serialise;
 pub mod tag;
 pub mod tree;
+pub mod tree_builder;
+
+use serialise::Serialise;
+
+// Checks a `<name> <<email>> <timestamp> <timezone>` signature line, as found in
+// the author, committer and tagger headers.
+pub(crate) fn validate_signature(header: &str, signature: &str) -> Result<(), String> {
+    let email_start = signature.find(" <");
+    let email_end = signature.find("> ");
+
+    let (email_start, email_end) = match (email_start, email_end) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return Err(format!("Malformed {} line: {:?}", header, signature)),
+    };
+
+    if email_start == 0 {
+        return Err(format!("{} line is missing a name", header));
+    }
+
+    let date: Vec<&str> = signature[email_end + 2..].split(' ').collect();
+
+    match date.as_slice() {
+        [timestamp, timezone]
+            if !timestamp.is_empty()
+                && timestamp.bytes().all(|byte| byte.is_ascii_digit())
+                && timezone.len() == 5
+                && (timezone.starts_with('+') || timezone.starts_with('-'))
+                && timezone[1..].bytes().all(|byte| byte.is_ascii_digit()) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!("Malformed {} date: {:?}", header, signature)),
+    }
+}
+
+// Strictly checks the content of an object of the given type, without panicking
+// on any input. Objects coming from other repositories should go through it,
+// and fuzzing harnesses can feed it arbitrary bytes.
+pub fn validate_object(object_type: &str, content: &[u8]) -> Result<(), String> {
+    let text = || {
+        std::str::from_utf8(content)
+            .map_err(|_| format!("{} object is not valid UTF-8", object_type))
+    };
+
+    match object_type {
+        "blob" => Ok(()),
+        "tree" => {
+            let tree = tree::Tree::parse(content)?;
+
+            // Entries are checked and in order once parsed, so writing them
+            // back only differs in how modes are written (`040000` for
+            // `40000`, which git fsck flags as zero-padded).
+            if tree.serialise() != content {
+                return Err(String::from("Tree has zero-padded file modes"));
+            }
+
+            Ok(())
+        }
+        "commit" => commit::Commit::validate(text()?),
+        "tag" => tag::Tag::validate(text()?).map(|_| ()),
+        _ => Err(format!("Unknown object type: {:?}", object_type)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA: &str = "6ff87c4664981e4397625791c8ea3bbb5f2279a3";
+    const SIGNATURE: &str = "A U Thor <author@example.com> 1666772992 +0100";
+
+    fn tree_entry(mode: &str, name: &str, sha: &str) -> Vec<u8> {
+        let mut entry = format!("{} {}\x00", mode, name).into_bytes();
+        entry.extend(oid::Oid::from_hex(sha).unwrap().as_bytes());
+
+        entry
+    }
+
+    fn commit(headers: &str) -> String {
+        format!("{}\nmessage\n", headers)
+    }
+
+    fn valid_commit() -> String {
+        commit(&format!(
+            "tree {}\nparent {}\nauthor {}\ncommitter {}\n",
+            SHA, SHA, SIGNATURE, SIGNATURE
+        ))
+    }
+
+    fn valid_tag() -> String {
+        format!(
+            "object {}\ntype commit\ntag v1.0\ntagger {}\n\nmessage\n",
+            SHA, SIGNATURE
+        )
+    }
+
+    fn valid_tree() -> Vec<u8> {
+        [
+            tree_entry("100644", "a.txt", SHA),
+            tree_entry("120000", "b", SHA),
+            tree_entry("100755", "foo.txt", SHA),
+            tree_entry("40000", "foo", SHA),
+            tree_entry("160000", "sub", SHA),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn accepts_valid_objects() {
+        assert_eq!(validate_object("blob", b"\xff\x00 anything"), Ok(()));
+        assert_eq!(validate_object("tree", b""), Ok(()));
+        assert_eq!(validate_object("tree", &valid_tree()), Ok(()));
+        assert_eq!(validate_object("commit", valid_commit().as_bytes()), Ok(()));
+        assert_eq!(validate_object("tag", valid_tag().as_bytes()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_truncated_object_ids() {
+        let tree = valid_tree();
+
+        for length in [tree.len() - 1, tree.len() - 19, 28] {
+            assert!(
+                validate_object("tree", &tree[..length]).is_err(),
+                "{}",
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unsorted_and_duplicate_entries() {
+        let unsorted = [
+            tree_entry("100644", "b", SHA),
+            tree_entry("100644", "a", SHA),
+        ];
+        let duplicate = [
+            tree_entry("100644", "a", SHA),
+            tree_entry("100644", "a", SHA),
+        ];
+        // A directory sorts as if its name ended with a slash.
+        let directory = [
+            tree_entry("40000", "foo", SHA),
+            tree_entry("100644", "foo.txt", SHA),
+        ];
+        let same_name = [
+            tree_entry("100644", "foo", SHA),
+            tree_entry("40000", "foo", SHA),
+        ];
+
+        for entries in [unsorted, duplicate, directory, same_name] {
+            assert!(validate_object("tree", &entries.concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_bad_modes() {
+        for mode in [
+            "100664", "644", "0100644", "10064x", "", "-100644", "160001",
+        ] {
+            let tree = tree_entry(mode, "a", SHA);
+            assert!(validate_object("tree", &tree).is_err(), "{:?}", mode);
+        }
+
+        let padded = tree_entry("040000", "a", SHA);
+        assert!(tree::Tree::parse(&padded).is_ok());
+        assert_eq!(
+            validate_object("tree", &padded),
+            Err(String::from("Tree has zero-padded file modes"))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        for name in ["", ".", "..", "a/b"] {
+            let tree = tree_entry("100644", name, SHA);
+            assert!(validate_object("tree", &tree).is_err(), "{:?}", name);
+        }
+
+        assert!(validate_object("tree", b"100644 \xff\x00aaaaaaaaaaaaaaaaaaaa").is_err());
+        assert!(validate_object("tree", b"100644 name-without-nul").is_err());
+        assert!(validate_object("tree", b"100644").is_err());
+    }
+
+    #[test]
+    fn parses_header_continuations() {
+        let content = format!(
+            "tree {}\nauthor {}\ncommitter {}\ngpgsig -----BEGIN PGP SIGNATURE-----\n \n line\n -----END PGP SIGNATURE-----\n\nmessage\n",
+            SHA, SIGNATURE, SIGNATURE
+        );
+
+        assert_eq!(validate_object("commit", content.as_bytes()), Ok(()));
+
+        let kvlm = crate::git_object::kvlm_parser(&content).unwrap();
+        assert_eq!(
+            kvlm[3],
+            (
+                "gpgsig",
+                String::from("-----BEGIN PGP SIGNATURE-----\n\nline\n-----END PGP SIGNATURE-----")
+            )
+        );
+        assert_eq!(kvlm[4], ("", String::from("message\n")));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let cases = [
+            // A continuation with no header before it.
+            format!(" tree {}\n\n", SHA),
+            // A header without a value.
+            format!("tree\nauthor {}\ncommitter {}\n\n", SIGNATURE, SIGNATURE),
+            // A header line that does not end.
+            format!("tree {}", SHA),
+        ];
+
+        for content in cases {
+            assert!(
+                validate_object("commit", content.as_bytes()).is_err(),
+                "{:?}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_commits_with_missing_or_misplaced_headers() {
+        let tree = format!("tree {}\n", SHA);
+        let parent = format!("parent {}\n", SHA);
+        let author = format!("author {}\n", SIGNATURE);
+        let committer = format!("committer {}\n", SIGNATURE);
+
+        let cases = [
+            [author.as_str(), &committer].concat(),
+            [tree.as_str(), &committer].concat(),
+            [tree.as_str(), &author].concat(),
+            [tree.as_str(), &author, &parent, &committer].concat(),
+            [tree.as_str(), &author, &committer, &tree].concat(),
+            [parent.as_str(), &tree, &author, &committer].concat(),
+            format!("tree {}\n{}{}", &SHA[1..], author, committer),
+            [
+                tree.as_str(),
+                "author A U Thor 1666772992 +0100\n",
+                &committer,
+            ]
+            .concat(),
+            [
+                tree.as_str(),
+                &author,
+                "committer A <a@b> yesterday +0100\n",
+            ]
+            .concat(),
+        ];
+
+        for headers in cases {
+            assert!(
+                validate_object("commit", commit(&headers).as_bytes()).is_err(),
+                "{:?}",
+                headers
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        let valid = valid_tag();
+        let cases = [
+            valid.replace("type commit", "type potato"),
+            valid.replace("tag v1.0", "tag v1..0"),
+            valid.replace(&format!("tagger {}\n", SIGNATURE), ""),
+            valid.replace("object ", "object x"),
+            valid.replacen("\n\n", "\nextra header\n\n", 1),
+        ];
+
+        for content in cases {
+            assert!(
+                validate_object("tag", content.as_bytes()).is_err(),
+                "{:?}",
+                content
+            );
+        }
+
+        assert!(validate_object("tag", b"\xff").is_err());
+        assert!(validate_object("potato", b"").is_err());
+    }
+
+    // Mutates valid objects at random (with a fixed seed, so failures can be
+    // reproduced), checking the validators never panic.
+    #[test]
+    fn survives_random_mutations() {
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let mut random = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            (state % bound as u64) as usize
+        };
+
+        let objects = [
+            ("tree", valid_tree()),
+            ("commit", valid_commit().into_bytes()),
+            ("tag", valid_tag().into_bytes()),
+        ];
+
+        for _ in 0..20000 {
+            let (object_type, object) = &objects[random(objects.len())];
+            let mut content = object.clone();
+
+            for _ in 0..1 + random(4) {
+                let index = random(content.len() + 1);
+
+                match random(3) {
+                    0 if index < content.len() => content[index] = random(256) as u8,
+                    1 if index < content.len() => {
+                        content.remove(index);
+                    }
+                    _ => content.insert(index, b" \n\x00/0a"[random(6)]),
+                }
+            }
+
+            let _ = validate_object(object_type, &content);
+        }
+    }
+}
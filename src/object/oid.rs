@@ -0,0 +1,49 @@
+use std::fmt;
+
+// The id of an object: the SHA-1 of its encoded header and content.
+// Hashes are passed around as hex strings in most of the codebase, but tree
+// entries store the raw 20 bytes, so we need a type that can go both ways.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Oid([u8; 20]);
+
+impl Oid {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let raw: [u8; 20] = bytes
+            .try_into()
+            .map_err(|_| "Object id must be exactly 20 bytes long")?;
+
+        Ok(Oid(raw))
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, &'static str> {
+        if hex.len() != 40 {
+            return Err("Object id must be exactly 40 hexadecimal characters long");
+        }
+
+        if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err("Object id contains non-hexadecimal characters");
+        }
+
+        let mut raw = [0u8; 20];
+
+        for (index, byte) in raw.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap();
+        }
+
+        Ok(Oid(raw))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
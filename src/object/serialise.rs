@@ -1,6 +1,6 @@
 pub trait Serialise {
-    fn serialise(&self) -> &String;
-    fn content_with_headers(&self) -> String;
+    fn serialise(&self) -> Vec<u8>;
+    fn content_with_headers(&self) -> Vec<u8>;
     fn encoded_header(&self) -> String;
     fn hash(&self) -> String;
 }
@@ -1,23 +1,56 @@
-use super::serialise::Serialise;
+use super::{oid::Oid, serialise::Serialise, validate_signature};
+use crate::{git_object::kvlm_parser, validate::validate_ref_name};
 use sha1::{Digest, Sha1};
 
 pub struct Tag {
     content: String,
 }
 
+impl Tag {
+    pub fn new(content: String) -> Self {
+        Tag { content }
+    }
+
+    // Validates the raw contents of a tag object, which must have exactly the
+    // object, type, tag and tagger headers (in that order) followed by a blank
+    // line and the message. Returns the tagged object id and its type.
+    pub fn validate(content: &str) -> Result<(Oid, String), String> {
+        let kvlm = kvlm_parser(content)?;
+        let headers: Vec<&str> = kvlm.iter().map(|(key, _)| *key).collect();
+
+        if headers != ["object", "type", "tag", "tagger", ""] {
+            return Err(String::from(
+                "Tag must have object, type, tag and tagger headers followed by a message",
+            ));
+        }
+
+        let object = Oid::from_hex(&kvlm[0].1)?;
+        let object_type = kvlm[1].1.clone();
+
+        if !["commit", "tree", "blob", "tag"].contains(&object_type.as_str()) {
+            return Err(format!("Unknown tagged object type: {:?}", object_type));
+        }
+
+        validate_ref_name(&format!("refs/tags/{}", kvlm[2].1))?;
+        validate_signature("tagger", &kvlm[3].1)?;
+
+        Ok((object, object_type))
+    }
+}
+
 impl Serialise for Tag {
-    fn serialise(&self) -> &String {
-        &self.content
+    fn serialise(&self) -> Vec<u8> {
+        self.content.as_bytes().to_vec()
     }
 
-    fn content_with_headers(&self) -> String {
-        format!("{}{}", self.encoded_header(), self.serialise())
+    fn content_with_headers(&self) -> Vec<u8> {
+        [self.encoded_header().into_bytes(), self.serialise()].concat()
     }
 
     fn encoded_header(&self) -> String {
         let content = self.serialise();
 
-        format!("{} {}\x00", "blob", content.len())
+        format!("{} {}\x00", "tag", content.len())
     }
 
     fn hash(&self) -> String {
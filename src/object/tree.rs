@@ -1,23 +1,167 @@
-use super::serialise::Serialise;
+use super::{oid::Oid, serialise::Serialise};
 use sha1::{Digest, Sha1};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileMode {
+    Blob,
+    Executable,
+    Symlink,
+    Tree,
+    Commit,
+}
+
+impl FileMode {
+    // Returns the mode as it is written in tree objects. Note that git writes
+    // trees as "40000", without the leading zero.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileMode::Blob => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+            FileMode::Tree => "40000",
+            FileMode::Commit => "160000",
+        }
+    }
+
+    // Parses a mode as found in tree objects or ls-tree output. Trees are
+    // accepted both with and without the leading zero.
+    pub fn from_mode_str(mode: &str) -> Result<Self, String> {
+        match mode {
+            "100644" => Ok(FileMode::Blob),
+            "100755" => Ok(FileMode::Executable),
+            "120000" => Ok(FileMode::Symlink),
+            "40000" | "040000" => Ok(FileMode::Tree),
+            "160000" => Ok(FileMode::Commit),
+            _ => Err(format!("Invalid file mode: {:?}", mode)),
+        }
+    }
+
+    // Returns the type of the object an entry with this mode points to.
+    pub fn object_type(&self) -> &'static str {
+        match self {
+            FileMode::Blob | FileMode::Executable | FileMode::Symlink => "blob",
+            FileMode::Tree => "tree",
+            FileMode::Commit => "commit",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: FileMode,
+    pub name: String,
+    pub oid: Oid,
+}
+
+impl TreeEntry {
+    // Git sorts tree entries by name, but compares subtrees as if their name
+    // had a trailing slash (so "foo.txt" comes before the "foo" directory).
+    pub(crate) fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.name.as_bytes().to_vec();
+
+        if self.mode == FileMode::Tree {
+            key.push(b'/');
+        }
+
+        key
+    }
+}
+
 pub struct Tree {
-    content: String,
+    entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub fn new(mut entries: Vec<TreeEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.sort_key());
+
+        Tree { entries }
+    }
+
+    // Parses the binary contents of a tree object (see serialise). Entries must
+    // have a valid mode and name, and come sorted without duplicates, as git
+    // writes them.
+    pub fn parse(content: &[u8]) -> Result<Self, String> {
+        let mut entries: Vec<TreeEntry> = vec![];
+        let mut rest = content;
+
+        while !rest.is_empty() {
+            let offset = content.len() - rest.len();
+            let malformed =
+                |reason: &str| format!("Malformed tree entry at byte {}: {}", offset, reason);
+
+            let space = rest
+                .iter()
+                .position(|byte| *byte == b' ')
+                .ok_or_else(|| malformed("missing mode"))?;
+            let nul = rest
+                .iter()
+                .position(|byte| *byte == b'\x00')
+                .filter(|nul| *nul > space)
+                .ok_or_else(|| malformed("missing name"))?;
+
+            let mode = std::str::from_utf8(&rest[..space])
+                .map_err(|_| malformed("invalid mode"))
+                .and_then(|mode| {
+                    FileMode::from_mode_str(mode).map_err(|error| malformed(&error))
+                })?;
+            let name = std::str::from_utf8(&rest[space + 1..nul])
+                .map_err(|_| malformed("name is not valid UTF-8"))?;
+
+            if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+                return Err(malformed(&format!("invalid name {:?}", name)));
+            }
+
+            let oid = rest
+                .get(nul + 1..nul + 21)
+                .ok_or_else(|| malformed("truncated object id"))
+                .and_then(|bytes| Oid::from_bytes(bytes).map_err(malformed))?;
+
+            let entry = TreeEntry {
+                mode,
+                name: name.to_owned(),
+                oid,
+            };
+
+            if let Some(previous) = entries.last() {
+                if previous.sort_key() >= entry.sort_key() || previous.name == entry.name {
+                    return Err(malformed(&format!(
+                        "{:?} is duplicated or out of order",
+                        entry.name
+                    )));
+                }
+            }
+
+            entries.push(entry);
+            rest = &rest[nul + 21..];
+        }
+
+        Ok(Tree { entries })
+    }
+
+    pub fn entries(&self) -> &[TreeEntry] {
+        &self.entries
+    }
 }
 
 impl Serialise for Tree {
-    fn serialise(&self) -> &String {
-        &self.content
+    // Each entry is serialised as `<mode> <name>\0<20 byte binary sha>`.
+    fn serialise(&self) -> Vec<u8> {
+        self.entries.iter().fold(vec![], |mut acc, entry| {
+            acc.extend(format!("{} {}\x00", entry.mode.as_str(), entry.name).as_bytes());
+            acc.extend(entry.oid.as_bytes());
+            acc
+        })
     }
 
-    fn content_with_headers(&self) -> String {
-        format!("{}{}", self.encoded_header(), self.serialise())
+    fn content_with_headers(&self) -> Vec<u8> {
+        [self.encoded_header().into_bytes(), self.serialise()].concat()
     }
 
     fn encoded_header(&self) -> String {
         let content = self.serialise();
 
-        format!("{} {}\x00", "blob", content.len())
+        format!("{} {}\x00", "tree", content.len())
     }
 
     fn hash(&self) -> String {
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use super::{
+    oid::Oid,
+    tree::{FileMode, Tree, TreeEntry},
+};
+use crate::{repository::Repository, validate::validate_tree_path};
+
+pub enum TreeBuilderEntry {
+    File { mode: FileMode, oid: Oid },
+    Tree(TreeBuilder),
+}
+
+// Builds a tree (and all of its subtrees) incrementally from slash separated
+// paths, so callers never have to deal with the binary tree format themselves.
+//
+// ```
+// let mut builder = TreeBuilder::new();
+// builder.insert("src/main.rs", FileMode::Blob, blob_oid)?;
+// let tree_oid = builder.write(&repo);
+// ```
+#[derive(Default)]
+pub struct TreeBuilder {
+    entries: BTreeMap<String, TreeBuilderEntry>,
+}
+
+// Splits a path into its components, rejecting anything that could not be
+// stored as a tree entry name.
+fn split_path(path: &str) -> Result<Vec<&str>, String> {
+    validate_tree_path(path)?;
+
+    Ok(path.split('/').collect())
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        TreeBuilder {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Inserts a file at the given path, creating any intermediate subtrees.
+    // An existing file at the same path is replaced.
+    pub fn insert(&mut self, path: &str, mode: FileMode, oid: Oid) -> Result<(), String> {
+        let components = split_path(path)?;
+        let (name, parents) = components.split_last().unwrap();
+        let mut current = self;
+
+        for parent in parents {
+            let entry = current
+                .entries
+                .entry(parent.to_string())
+                .or_insert_with(|| TreeBuilderEntry::Tree(TreeBuilder::new()));
+
+            current = match entry {
+                TreeBuilderEntry::Tree(subtree) => subtree,
+                TreeBuilderEntry::File { .. } => {
+                    return Err(format!(
+                        "Could not insert {:?} because {:?} is not a directory",
+                        path, parent
+                    ))
+                }
+            };
+        }
+
+        if let Some(TreeBuilderEntry::Tree(_)) = current.entries.get(*name) {
+            return Err(format!(
+                "Could not insert {:?} because it is already a directory",
+                path
+            ));
+        }
+
+        current
+            .entries
+            .insert(name.to_string(), TreeBuilderEntry::File { mode, oid });
+
+        Ok(())
+    }
+
+    // Removes the entry at the given path (file or whole subtree) and prunes
+    // any parent subtrees that are left empty.
+    pub fn remove(&mut self, path: &str) -> Option<TreeBuilderEntry> {
+        let components = split_path(path).ok()?;
+
+        self.remove_components(&components)
+    }
+
+    fn remove_components(&mut self, components: &[&str]) -> Option<TreeBuilderEntry> {
+        let (name, rest) = components.split_first()?;
+
+        if rest.is_empty() {
+            return self.entries.remove(*name);
+        }
+
+        match self.entries.get_mut(*name) {
+            Some(TreeBuilderEntry::Tree(subtree)) => {
+                let removed = subtree.remove_components(rest);
+
+                if subtree.is_empty() {
+                    self.entries.remove(*name);
+                }
+
+                removed
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&TreeBuilderEntry> {
+        let components = split_path(path).ok()?;
+        let (name, parents) = components.split_last()?;
+        let mut current = self;
+
+        for parent in parents {
+            current = match current.entries.get(*parent) {
+                Some(TreeBuilderEntry::Tree(subtree)) => subtree,
+                _ => return None,
+            };
+        }
+
+        current.entries.get(*name)
+    }
+
+    // Writes every subtree and then this tree to the repository, returning
+    // the id of the resulting tree. Empty subtrees are skipped, like git does.
+    pub fn write(&self, repo: &Repository) -> Oid {
+        let entries = self
+            .entries
+            .iter()
+            .filter_map(|(name, entry)| match entry {
+                TreeBuilderEntry::File { mode, oid } => Some(TreeEntry {
+                    mode: *mode,
+                    name: name.to_owned(),
+                    oid: *oid,
+                }),
+                TreeBuilderEntry::Tree(subtree) if !subtree.is_empty() => Some(TreeEntry {
+                    mode: FileMode::Tree,
+                    name: name.to_owned(),
+                    oid: subtree.write(repo),
+                }),
+                TreeBuilderEntry::Tree(_) => None,
+            })
+            .collect();
+
+        let hash = repo.object_write(&Tree::new(entries), true);
+
+        Oid::from_hex(&hash).expect("Object hash is not a valid object id")
+    }
+}
@@ -0,0 +1,300 @@
+// Pathspecs select the paths a command operates on. Patterns are relative to
+// the worktree root and match a path when:
+//
+// - they are equal to it, or name one of its leading directories ("src"
+//   matches "src/main.rs"), or
+// - they contain wildcards (`*`, `?`, `[...]`) that match it. As in git, `*`
+//   also matches slashes, so "*.rs" matches "src/main.rs".
+//
+// A pattern can be prefixed with magic words to change how it matches:
+// `:(icase)` (case insensitive), `:(literal)` (no wildcards), `:(exclude)`
+// (remove matching paths from the result, also written `:!` or `:^`) and
+// `:(top)` (also written `:/`, accepted for compatibility as patterns are
+// always relative to the top of the worktree). Several can be combined with
+// commas: `:(exclude,icase)*.md`.
+
+#[derive(Debug)]
+struct PathspecItem {
+    pattern: String,
+    icase: bool,
+    literal: bool,
+    exclude: bool,
+}
+
+#[derive(Debug)]
+pub struct Pathspec {
+    items: Vec<PathspecItem>,
+}
+
+fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '\\'])
+}
+
+// Matches a bracket expression starting right after the '[', returning whether
+// it matched and the pattern left after the closing ']'.
+fn match_bracket(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match pattern.first() {
+        Some('!') | Some('^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        match rest {
+            [']', tail @ ..] if !first => return Some((matched != negated, tail)),
+            [low, '-', high, tail @ ..] if *high != ']' => {
+                matched |= *low <= c && c <= *high;
+                rest = tail;
+            }
+            [other, tail @ ..] => {
+                matched |= *other == c;
+                rest = tail;
+            }
+            // Unterminated bracket expressions never match.
+            [] => return None,
+        }
+
+        first = false;
+    }
+}
+
+// Matches text against a wildcard pattern. As `*` matches any run of
+// characters, slashes included, when the pattern fails after a `*` only that
+// last `*` needs retrying, one character further each time: earlier ones could
+// not let more of the pattern match. This keeps matching quadratic at worst
+// instead of exponential in the number of `*`.
+fn wildmatch(pattern: &[char], text: &[char]) -> bool {
+    let (mut pattern, mut text) = (pattern, text);
+    // The pattern after the last `*` and the text it was last tried against.
+    let mut backtrack: Option<(&[char], &[char])> = None;
+
+    loop {
+        if let ['*', rest @ ..] = pattern {
+            pattern = rest;
+            backtrack = Some((rest, text));
+            continue;
+        }
+
+        if pattern.is_empty() && text.is_empty() {
+            return true;
+        }
+
+        let step = match (pattern, text.split_first()) {
+            (['?', rest @ ..], Some((_, text_rest))) => Some((rest, text_rest)),
+            (['[', rest @ ..], Some((c, text_rest))) => match match_bracket(rest, *c) {
+                Some((true, tail)) => Some((tail, text_rest)),
+                _ => None,
+            },
+            (['\\', escaped, rest @ ..], Some((c, text_rest))) if escaped == c => {
+                Some((rest, text_rest))
+            }
+            (['\\', _, ..], _) => None,
+            ([p, rest @ ..], Some((c, text_rest))) if p == c => Some((rest, text_rest)),
+            _ => None,
+        };
+
+        match (step, backtrack) {
+            (Some((pattern_rest, text_rest)), _) => {
+                pattern = pattern_rest;
+                text = text_rest;
+            }
+            (None, Some((after_star, [_, retry @ ..]))) => {
+                backtrack = Some((after_star, retry));
+                pattern = after_star;
+                text = retry;
+            }
+            (None, _) => return false,
+        }
+    }
+}
+
+impl PathspecItem {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut item = PathspecItem {
+            pattern: spec.to_owned(),
+            icase: false,
+            literal: false,
+            exclude: false,
+        };
+
+        if let Some(long_magic) = spec.strip_prefix(":(") {
+            let (magic, pattern) = long_magic
+                .split_once(')')
+                .ok_or_else(|| format!("Missing ')' at the end of pathspec magic in {:?}", spec))?;
+
+            for word in magic.split(',').filter(|word| !word.is_empty()) {
+                match word {
+                    "icase" => item.icase = true,
+                    "literal" => item.literal = true,
+                    "exclude" => item.exclude = true,
+                    "top" => {}
+                    _ => return Err(format!("Invalid pathspec magic {:?} in {:?}", word, spec)),
+                }
+            }
+
+            item.pattern = pattern.to_owned();
+        } else if let Some(short_magic) = spec.strip_prefix(':') {
+            let pattern = short_magic.trim_start_matches(['!', '^', '/']);
+            let magic = &short_magic[..short_magic.len() - pattern.len()];
+
+            item.exclude = magic.contains(['!', '^']);
+            item.pattern = pattern.strip_prefix(':').unwrap_or(pattern).to_owned();
+        }
+
+        if item.pattern == "." {
+            item.pattern = String::new();
+        }
+
+        Ok(item)
+    }
+
+    // Returns the pattern and the path to compare, both lowercased for icase.
+    fn normalise(&self, path: &str) -> (String, String) {
+        if self.icase {
+            (self.pattern.to_lowercase(), path.to_lowercase())
+        } else {
+            (self.pattern.clone(), path.to_owned())
+        }
+    }
+
+    // Returns true if the pattern is the path or one of its leading
+    // directories, in which case it matches everything inside the path too.
+    fn matches_leading(&self, path: &str) -> bool {
+        let (pattern, path) = self.normalise(path);
+        let directory = pattern.trim_end_matches('/');
+
+        pattern.is_empty()
+            || path == directory
+            || (path.starts_with(directory) && path[directory.len()..].starts_with('/'))
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if self.matches_leading(path) {
+            return true;
+        }
+
+        let (pattern, path) = self.normalise(path);
+
+        if self.literal || !has_wildcards(&pattern) {
+            return false;
+        }
+
+        let pattern: Vec<char> = pattern.chars().collect();
+        let path: Vec<char> = path.chars().collect();
+
+        wildmatch(&pattern, &path)
+    }
+
+    // Returns true if the pattern may match paths inside the directory: it
+    // names the directory or a path inside it, or the part of it before the
+    // first wildcard could continue into the directory.
+    fn may_match_in(&self, directory: &str) -> bool {
+        if self.matches_leading(directory) {
+            return true;
+        }
+
+        let (pattern, directory) = self.normalise(directory);
+        let directory = format!("{}/", directory);
+        let wildcards = !self.literal && has_wildcards(&pattern);
+        let prefix = match pattern.find(['*', '?', '[', '\\']) {
+            Some(index) if wildcards => &pattern[..index],
+            _ => &pattern,
+        };
+
+        prefix.starts_with(&directory) || (wildcards && directory.starts_with(prefix))
+    }
+}
+
+impl Pathspec {
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let items = specs
+            .iter()
+            .map(|spec| PathspecItem::parse(spec))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Pathspec { items })
+    }
+
+    // Returns true if the slash separated, worktree relative path is selected.
+    // An empty pathspec (or one with only exclusions) selects every path not
+    // explicitly excluded.
+    pub fn matches(&self, path: &str) -> bool {
+        let (excludes, includes): (Vec<&PathspecItem>, Vec<&PathspecItem>) =
+            self.items.iter().partition(|item| item.exclude);
+
+        (includes.is_empty() || includes.iter().any(|item| item.matches(path)))
+            && !excludes.iter().any(|item| item.matches(path))
+    }
+
+    // Returns true if the pathspec may select paths inside the directory, so
+    // that directories it cannot select anything in can be skipped. It can
+    // return true for a directory where nothing ends up selected, but never
+    // false for one where something is.
+    pub fn may_match_in(&self, directory: &str) -> bool {
+        let (excludes, includes): (Vec<&PathspecItem>, Vec<&PathspecItem>) =
+            self.items.iter().partition(|item| item.exclude);
+
+        (includes.is_empty() || includes.iter().any(|item| item.may_match_in(directory)))
+            && !excludes.iter().any(|item| item.matches_leading(directory))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Pathspec::parse(&[pattern.to_owned()])
+            .unwrap()
+            .matches(path)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "src/main.rs"));
+        assert!(matches("src/*", "src/a/b.rs"));
+        assert!(matches("*a*b*c", "xaybzc"));
+        assert!(!matches("*a*b*c", "xaybzcd"));
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", ".txt"));
+        assert!(matches("[a-c]x[!y]", "bxz"));
+        assert!(!matches("[a-c]x[!y]", "bxy"));
+        assert!(!matches("[ab", "a"));
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+        assert!(matches("*", ""));
+        assert!(matches("a**", "a"));
+    }
+
+    #[test]
+    fn may_match_in_directories() {
+        let may_match_in = |patterns: &[&str], directory: &str| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+
+            Pathspec::parse(&patterns).unwrap().may_match_in(directory)
+        };
+
+        assert!(may_match_in(&[], "dir"));
+        assert!(may_match_in(&["dir/file"], "dir"));
+        assert!(may_match_in(&["dir"], "dir/sub"));
+        assert!(may_match_in(&["d*"], "dir"));
+        assert!(may_match_in(&["*.rs"], "dir"));
+        assert!(may_match_in(&["dir/*.rs"], "dir"));
+        assert!(may_match_in(&[":(icase)DIR/file"], "dir"));
+        assert!(!may_match_in(&["dirx/file"], "dir"));
+        assert!(!may_match_in(&["other/*.rs"], "dir"));
+        assert!(!may_match_in(&[":(literal)d*"], "dir"));
+        assert!(!may_match_in(&[":!dir"], "dir/sub"));
+        assert!(may_match_in(&[":!dir/file"], "dir"));
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_exponentially() {
+        let pattern = "*a".repeat(30) + "b";
+        let path = "a".repeat(100);
+
+        assert!(!matches(&pattern, &path));
+        assert!(matches(&pattern, &(path + "b")));
+    }
+}
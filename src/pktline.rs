@@ -0,0 +1,450 @@
+use std::io::{self, Read, Write};
+
+// Git's pkt-line framing, used by the transfer protocols and by long-running
+// filter processes.
+//
+// Every packet starts with its total length (including the 4 length bytes) as
+// 4 hexadecimal digits, followed by the payload. A few lengths below 4 are
+// special packets with no payload:
+//
+// ```
+// 000dhello world   data packet "hello world" plus line feed (13 bytes)
+// 0000              flush packet, ends a message
+// 0001              delimiter packet, separates sections (protocol v2)
+// 0002              response end packet (protocol v2, stateless connections)
+// ```
+
+// Largest packet allowed, length prefix included.
+pub const MAX_PACKET_LENGTH: usize = 65520;
+// Largest payload a data packet can carry.
+pub const MAX_PACKET_DATA: usize = MAX_PACKET_LENGTH - 4;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet {
+    Data(Vec<u8>),
+    Flush,
+    Delim,
+    ResponseEnd,
+}
+
+// Side-band channels multiplexed over data packets. The first byte of the
+// payload tells which channel the rest of it belongs to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SideBand {
+    Data(Vec<u8>),
+    Progress(Vec<u8>),
+    Error(Vec<u8>),
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Packet {
+    // Returns the payload of a data packet with the trailing line feed removed,
+    // which is how text packets are sent.
+    pub fn as_text(&self) -> Option<&[u8]> {
+        match self {
+            Packet::Data(data) => Some(data.strip_suffix(b"\n").unwrap_or(data)),
+            _ => None,
+        }
+    }
+}
+
+impl SideBand {
+    pub fn demultiplex(packet: Packet) -> io::Result<Self> {
+        let data = match packet {
+            Packet::Data(data) => data,
+            other => {
+                return Err(invalid_data(format!(
+                    "Expected a side-band data packet, got {:?}",
+                    other
+                )))
+            }
+        };
+
+        match data.split_first() {
+            Some((1, payload)) => Ok(SideBand::Data(payload.to_vec())),
+            Some((2, payload)) => Ok(SideBand::Progress(payload.to_vec())),
+            Some((3, payload)) => Ok(SideBand::Error(payload.to_vec())),
+            Some((band, _)) => Err(invalid_data(format!("Invalid side-band channel {}", band))),
+            None => Err(invalid_data(String::from("Empty side-band packet"))),
+        }
+    }
+}
+
+pub struct PktLineReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> PktLineReader<R> {
+    pub fn new(inner: R) -> Self {
+        PktLineReader { inner }
+    }
+
+    // Reads the next packet, or None if the stream ended cleanly before it.
+    pub fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut length_bytes = [0u8; 4];
+
+        // Only a stream ending right before a packet is a clean end, one ending
+        // in the middle of the length is truncated.
+        loop {
+            match self.inner.read(&mut length_bytes[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        self.inner.read_exact(&mut length_bytes[1..])?;
+
+        let length = std::str::from_utf8(&length_bytes)
+            .ok()
+            .filter(|length| length.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .and_then(|length| usize::from_str_radix(length, 16).ok())
+            .ok_or_else(|| invalid_data(format!("Invalid packet length {:?}", length_bytes)))?;
+
+        match length {
+            0 => Ok(Some(Packet::Flush)),
+            1 => Ok(Some(Packet::Delim)),
+            2 => Ok(Some(Packet::ResponseEnd)),
+            3 => Err(invalid_data(String::from("Invalid packet length 0003"))),
+            _ if length > MAX_PACKET_LENGTH => Err(invalid_data(format!(
+                "Packet length {} exceeds the maximum of {}",
+                length, MAX_PACKET_LENGTH
+            ))),
+            _ => {
+                let mut data = vec![0u8; length - 4];
+                self.inner.read_exact(&mut data)?;
+
+                Ok(Some(Packet::Data(data)))
+            }
+        }
+    }
+
+    // Reads data packets until a flush packet, returning their payloads. Fails
+    // if the stream ends before the flush.
+    pub fn read_until_flush(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut payloads = vec![];
+
+        loop {
+            match self.read_packet()? {
+                Some(Packet::Data(data)) => payloads.push(data),
+                Some(Packet::Flush) => return Ok(payloads),
+                Some(other) => {
+                    return Err(invalid_data(format!(
+                        "Unexpected {:?} packet before flush",
+                        other
+                    )))
+                }
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Stream ended before a flush packet",
+                    ))
+                }
+            }
+        }
+    }
+
+    // Reads side-band multiplexed packets until a flush packet, returning the
+    // data channel contents. Progress messages are handed to on_progress as
+    // they arrive; a message on the error channel aborts with that message.
+    // Fails if the stream ends before the flush.
+    pub fn read_side_band(&mut self, mut on_progress: impl FnMut(&[u8])) -> io::Result<Vec<u8>> {
+        let mut data = vec![];
+
+        loop {
+            let packet = match self.read_packet()? {
+                Some(Packet::Flush) => return Ok(data),
+                Some(packet) => packet,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Stream ended before a flush packet",
+                    ))
+                }
+            };
+
+            match SideBand::demultiplex(packet)? {
+                SideBand::Data(payload) => data.extend(payload),
+                SideBand::Progress(message) => on_progress(&message),
+                SideBand::Error(message) => {
+                    return Err(io::Error::other(format!(
+                        "Remote error: {}",
+                        String::from_utf8_lossy(&message).trim_end()
+                    )))
+                }
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+pub struct PktLineWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PktLineWriter<W> {
+    pub fn new(inner: W) -> Self {
+        PktLineWriter { inner }
+    }
+
+    pub fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() > MAX_PACKET_DATA {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Packet payload of {} bytes exceeds the maximum of {}",
+                    data.len(),
+                    MAX_PACKET_DATA
+                ),
+            ));
+        }
+
+        write!(self.inner, "{:04x}", data.len() + 4)?;
+        self.inner.write_all(data)
+    }
+
+    // Writes a text packet, which by convention ends with a line feed.
+    pub fn write_text(&mut self, text: &str) -> io::Result<()> {
+        if text.ends_with('\n') {
+            self.write_data(text.as_bytes())
+        } else {
+            self.write_data(format!("{}\n", text).as_bytes())
+        }
+    }
+
+    // Writes data on a side-band channel (1 data, 2 progress, 3 error),
+    // splitting it over as many packets as needed.
+    pub fn write_side_band(&mut self, band: u8, data: &[u8]) -> io::Result<()> {
+        if !(1..=3).contains(&band) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid side-band channel {}", band),
+            ));
+        }
+
+        for chunk in data.chunks(MAX_PACKET_DATA - 1) {
+            self.write_data(&[&[band], chunk].concat())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_flush(&mut self) -> io::Result<()> {
+        self.inner.write_all(b"0000")?;
+        self.inner.flush()
+    }
+
+    pub fn write_delim(&mut self) -> io::Result<()> {
+        self.inner.write_all(b"0001")
+    }
+
+    pub fn write_response_end(&mut self) -> io::Result<()> {
+        self.inner.write_all(b"0002")?;
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(bytes: &[u8]) -> PktLineReader<&[u8]> {
+        PktLineReader::new(bytes)
+    }
+
+    fn write(build: impl FnOnce(&mut PktLineWriter<Vec<u8>>) -> io::Result<()>) -> Vec<u8> {
+        let mut writer = PktLineWriter::new(vec![]);
+        build(&mut writer).unwrap();
+
+        writer.into_inner()
+    }
+
+    #[test]
+    fn writes_special_packets() {
+        let bytes = write(|writer| {
+            writer.write_text("hello world")?;
+            writer.write_delim()?;
+            writer.write_data(b"")?;
+            writer.write_flush()?;
+            writer.write_response_end()
+        });
+
+        assert_eq!(bytes, b"0010hello world\n0001000400000002");
+    }
+
+    #[test]
+    fn round_trips_packets() {
+        let bytes = write(|writer| {
+            writer.write_text("want 1234\n")?;
+            writer.write_data(b"\x00binary\xff")?;
+            writer.write_delim()?;
+            writer.write_flush()?;
+            writer.write_response_end()
+        });
+
+        let mut reader = reader(&bytes);
+        let mut packets = vec![];
+
+        while let Some(packet) = reader.read_packet().unwrap() {
+            packets.push(packet);
+        }
+
+        assert_eq!(packets[0].as_text(), Some(&b"want 1234"[..]));
+        assert_eq!(
+            packets[1..],
+            [
+                Packet::Data(b"\x00binary\xff".to_vec()),
+                Packet::Delim,
+                Packet::Flush,
+                Packet::ResponseEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_until_flush() {
+        let mut reader = reader(b"0006a\n0006b\n00000006c\n");
+
+        assert_eq!(
+            reader.read_until_flush().unwrap(),
+            [b"a\n".to_vec(), b"b\n".to_vec()]
+        );
+        assert_eq!(
+            reader.read_packet().unwrap(),
+            Some(Packet::Data(b"c\n".to_vec()))
+        );
+        assert_eq!(reader.read_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        for bytes in [
+            &b"00g5abc"[..],
+            b"-005a",
+            b" 005a",
+            b"0003",
+            b"fff1",
+            b"ffff",
+        ] {
+            let error = reader(bytes).read_packet().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        for bytes in [&b"00"[..], b"000ahello"] {
+            let error = reader(bytes).read_packet().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        for bytes in [&b"0006a\n"[..], b""] {
+            let error = reader(bytes).read_until_flush().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn accepts_packets_up_to_the_maximum_length() {
+        let data = vec![b'x'; MAX_PACKET_DATA];
+        let bytes = write(|writer| writer.write_data(&data));
+
+        assert_eq!(&bytes[..4], b"fff0");
+        assert_eq!(
+            reader(&bytes).read_packet().unwrap(),
+            Some(Packet::Data(data))
+        );
+
+        let mut writer = PktLineWriter::new(vec![]);
+        let error = writer.write_data(&[0; MAX_PACKET_DATA + 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn multiplexes_side_bands() {
+        let data = vec![b'd'; MAX_PACKET_DATA * 2];
+        let bytes = write(|writer| {
+            writer.write_side_band(2, b"Counting objects\r")?;
+            writer.write_side_band(1, &data)?;
+            writer.write_side_band(2, b"done\n")?;
+            writer.write_flush()
+        });
+
+        let mut progress = vec![];
+        let read = reader(&bytes)
+            .read_side_band(|message| progress.push(message.to_vec()))
+            .unwrap();
+
+        assert_eq!(read, data);
+        assert_eq!(
+            progress,
+            [b"Counting objects\r".to_vec(), b"done\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn demultiplexes_side_band_channels() {
+        let cases = [
+            (&b"\x01data"[..], SideBand::Data(b"data".to_vec())),
+            (b"\x02progress", SideBand::Progress(b"progress".to_vec())),
+            (b"\x03error", SideBand::Error(b"error".to_vec())),
+        ];
+
+        for (payload, expected) in cases {
+            assert_eq!(
+                SideBand::demultiplex(Packet::Data(payload.to_vec())).unwrap(),
+                expected
+            );
+        }
+
+        for packet in [
+            Packet::Data(b"\x04what".to_vec()),
+            Packet::Data(vec![]),
+            Packet::Flush,
+        ] {
+            assert!(SideBand::demultiplex(packet).is_err());
+        }
+    }
+
+    #[test]
+    fn reports_side_band_errors() {
+        let bytes = write(|writer| {
+            writer.write_side_band(1, b"partial")?;
+            writer.write_side_band(3, b"access denied\n")?;
+            writer.write_flush()
+        });
+
+        let error = reader(&bytes).read_side_band(|_| {}).unwrap_err();
+        assert_eq!(error.to_string(), "Remote error: access denied");
+    }
+
+    #[test]
+    fn rejects_truncated_side_band_streams() {
+        let bytes = write(|writer| writer.write_side_band(1, b"partial"));
+
+        let error = reader(&bytes).read_side_band(|_| {}).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_invalid_side_band_channels() {
+        for band in [0, 4, 255] {
+            let mut writer = PktLineWriter::new(vec![]);
+            let error = writer.write_side_band(band, b"data").unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(writer.into_inner().is_empty());
+        }
+    }
+}
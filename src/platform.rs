@@ -0,0 +1,226 @@
+use std::{
+    fs::rename,
+    io,
+    path::{Component, Path},
+    thread::sleep,
+    time::Duration,
+};
+
+// Platform differences we need to paper over, mostly for Windows. Everything is
+// compiled on all platforms and selected with cfg!(windows), so the Windows
+// paths are at least type checked everywhere.
+
+// Characters that cannot appear in file names on Windows.
+const WINDOWS_ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+// Device names that cannot be used as file names on Windows, with or without
+// an extension (NUL and NUL.txt are both reserved).
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Names that reach the repository directory on Windows: the directory itself,
+// and the 8.3 short names NTFS gives it. The .git ones are included because a
+// worktree may also be shared with git.
+const WINDOWS_REPOSITORY_NAMES: &[&str] = &[".got", "got~1", ".git", "git~1"];
+
+// How many times we retry a rename that Windows refused, usually because
+// another process (an antivirus, an editor) has the target file open.
+const RENAME_RETRIES: u32 = 5;
+
+// Converts a worktree relative path into a tree path, which always uses '/' as
+// separator regardless of the platform.
+pub fn to_tree_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Returns an error if a tree path cannot be written to the worktree on
+// Windows: reserved device names, illegal characters (including '\', which
+// Windows treats as a separator), components ending in a dot or a space (which
+// Windows silently strips, so "a." would alias "a") or names that alias the
+// repository directory.
+pub fn validate_windows_path(tree_path: &str) -> Result<(), String> {
+    for component in tree_path.split('/') {
+        let trimmed = component.trim_end_matches(['.', ' ']);
+
+        if WINDOWS_REPOSITORY_NAMES
+            .iter()
+            .any(|name| trimmed.eq_ignore_ascii_case(name))
+        {
+            return Err(format!(
+                "Path {:?} names the repository directory on Windows",
+                tree_path
+            ));
+        }
+
+        let stem = component.split('.').next().unwrap_or(component);
+
+        if WINDOWS_RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+        {
+            return Err(format!("Path {:?} uses a reserved Windows name", tree_path));
+        }
+
+        if component.contains(WINDOWS_ILLEGAL_CHARS)
+            || component.contains('\\')
+            || component.chars().any(|c| c.is_ascii_control())
+        {
+            return Err(format!(
+                "Path {:?} contains characters not allowed on Windows",
+                tree_path
+            ));
+        }
+
+        if component.ends_with('.') || component.ends_with(' ') {
+            return Err(format!(
+                "Path {:?} has a component ending in a dot or a space",
+                tree_path
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Checks a tree path before writing it to the worktree. Windows restrictions
+// only apply when running on Windows.
+pub fn validate_checkout_path(tree_path: &str) -> Result<(), String> {
+    if cfg!(windows) {
+        validate_windows_path(tree_path)
+    } else {
+        Ok(())
+    }
+}
+
+// Renames a file over an existing one. On Windows the rename fails while the
+// target is open by another process, so we retry a few times before giving up.
+pub fn rename_replace(from: &Path, to: &Path) -> io::Result<()> {
+    let retries = if cfg!(windows) { RENAME_RETRIES } else { 0 };
+    let mut attempt = 0;
+
+    loop {
+        match rename(from, to) {
+            Err(error) if attempt < retries && error.kind() == io::ErrorKind::PermissionDenied => {
+                attempt += 1;
+                sleep(Duration::from_millis(10 << attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+// Makes a file executable, or not, for everyone who can read it. Windows has no
+// executable bit, so there it does nothing.
+#[cfg(unix)]
+pub fn set_executable(path: &Path, executable: bool) -> io::Result<()> {
+    use std::{fs::set_permissions, os::unix::fs::PermissionsExt};
+
+    let mut permissions = path.metadata()?.permissions();
+    let mode = permissions.mode();
+
+    // Execute bits follow the read bits, as git does (0644 becomes 0755).
+    permissions.set_mode(if executable {
+        mode | (mode & 0o444) >> 2
+    } else {
+        mode & !0o111
+    });
+
+    set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+pub fn set_executable(_path: &Path, _executable: bool) -> io::Result<()> {
+    Ok(())
+}
+
+// Returns whether a file can be run as a program. Windows has no executable
+// bit, so there any file can.
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// Creates a symbolic link. Where creating them is not supported (or needs
+// special privileges, like on Windows) a plain file holding the target is
+// written instead, as git does with core.symlinks set to false.
+#[cfg(unix)]
+pub fn create_symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+pub fn create_symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::fs::write(path, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_ordinary_paths() {
+        for path in [
+            "README.md",
+            "src/main.rs",
+            "console.txt",
+            "COM10",
+            "auxiliary/file",
+            ".gitignore",
+            ".got-attributes",
+            "git~2",
+            "a.b.c",
+        ] {
+            assert_eq!(validate_windows_path(path), Ok(()), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_windows_paths() {
+        for (path, reason) in [
+            ("CON", "reserved Windows name"),
+            ("con", "reserved Windows name"),
+            ("aux.txt", "reserved Windows name"),
+            ("COM1", "reserved Windows name"),
+            ("dir/lpt9.log", "reserved Windows name"),
+            ("NUL .txt", "reserved Windows name"),
+            ("a:b", "characters not allowed"),
+            ("dir/a\\b", "characters not allowed"),
+            ("what?", "characters not allowed"),
+            ("tab\there", "characters not allowed"),
+            ("file.", "ending in a dot or a space"),
+            ("file ", "ending in a dot or a space"),
+            ("dir./file", "ending in a dot or a space"),
+            (".git", "repository directory"),
+            (".GIT", "repository directory"),
+            ("git~1/config", "repository directory"),
+            ("GIT~1", "repository directory"),
+            (".git. ", "repository directory"),
+            ("dir/.got", "repository directory"),
+            ("got~1", "repository directory"),
+            (".git::$INDEX_ALLOCATION/config", "characters not allowed"),
+        ] {
+            match validate_windows_path(path) {
+                Err(error) => assert!(error.contains(reason), "{}: {}", path, error),
+                Ok(()) => panic!("{} was accepted", path),
+            }
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    collections::HashSet,
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    platform::rename_replace,
+    repository::Repository,
+    validate::{validate_object_id, validate_ref_name},
+};
+
+// Refs are accessed through a RefStore, so the way they are stored can change
+// without touching their users. Updates are grouped in transactions that are
+// applied all or nothing, each update optionally checking the value the ref had
+// before, which lets concurrent writers detect they raced with each other.
+
+// Symbolic refs pointing to symbolic refs deeper than this are considered a loop.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+// What a ref is expected to hold before a transaction updates it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefExpectation {
+    Any,
+    Missing,
+    Value(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct RefUpdate {
+    pub name: String,
+    // None deletes the ref.
+    pub new_value: Option<String>,
+    pub expected: RefExpectation,
+}
+
+#[derive(Default)]
+pub struct RefTransaction {
+    updates: Vec<RefUpdate>,
+}
+
+impl RefTransaction {
+    pub fn new() -> Self {
+        RefTransaction::default()
+    }
+
+    pub fn update(&mut self, name: &str, new_value: &str, expected: RefExpectation) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_owned(),
+            new_value: Some(new_value.to_owned()),
+            expected,
+        });
+
+        self
+    }
+
+    pub fn delete(&mut self, name: &str, expected: RefExpectation) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_owned(),
+            new_value: None,
+            expected,
+        });
+
+        self
+    }
+
+    pub fn updates(&self) -> &[RefUpdate] {
+        &self.updates
+    }
+
+    // Checks the names and values of every update, and that no ref is updated
+    // twice, before anything is written.
+    fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+
+        for update in &self.updates {
+            validate_ref_name(&update.name)?;
+
+            if let Some(new_value) = &update.new_value {
+                validate_object_id(new_value)?;
+            }
+
+            if let RefExpectation::Value(old_value) = &update.expected {
+                validate_object_id(old_value)?;
+            }
+
+            if !names.insert(update.name.as_str()) {
+                return Err(format!(
+                    "Ref {} is updated twice in a transaction",
+                    update.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Stores are Send + Sync so a Repository and its stores can be shared between
+// threads.
+pub trait RefStore: Send + Sync {
+    // Returns the object id a ref points to, following symbolic refs.
+    fn read(&self, name: &str) -> Option<String>;
+
+    // Lists the names of all refs under a given prefix (e.g. "refs/tags"),
+    // sorted alphabetically.
+    fn list(&self, prefix: &str) -> Vec<String>;
+
+    // Applies every update of the transaction, or none of them if any fails.
+    fn commit(&self, transaction: &RefTransaction) -> Result<(), String>;
+}
+
+// Refs stored as loose files under .got, containing either an object id or a
+// symbolic reference to another ref (`ref: refs/heads/master`).
+pub struct FilesRefStore {
+    root: PathBuf,
+}
+
+fn list_refs_in_path(path: &Path, name: &str, refs: &mut Vec<String>) {
+    let entries = match read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let entry_name = format!("{}/{}", name, entry.file_name().to_string_lossy());
+
+        if entry.path().is_dir() {
+            list_refs_in_path(&entry.path(), &entry_name, refs);
+        } else if !entry_name.ends_with(".lock") {
+            refs.push(entry_name);
+        }
+    }
+}
+
+impl FilesRefStore {
+    pub fn new(root: PathBuf) -> Self {
+        FilesRefStore { root }
+    }
+
+    // Ref names are validated before being turned into paths, so they cannot
+    // escape the root.
+    fn ref_path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    fn lock_path(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{}.lock", name))
+    }
+
+    fn read_with_depth(&self, name: &str, depth: usize) -> Option<String> {
+        validate_ref_name(name).ok()?;
+
+        let content = read_to_string(self.ref_path(name)).ok()?;
+        let content = content.trim();
+
+        match content.strip_prefix("ref: ") {
+            Some(target) if depth < MAX_SYMREF_DEPTH => self.read_with_depth(target, depth + 1),
+            Some(_) => None,
+            None => Some(content.to_owned()),
+        }
+    }
+
+    // Creates the lock file of a ref. Creating it fails if it already exists,
+    // which means someone else is updating the same ref.
+    fn lock(&self, update: &RefUpdate) -> Result<(), String> {
+        let lock_path = self.lock_path(&update.name);
+        create_dir_all(lock_path.parent().unwrap()).map_err(|error| error.to_string())?;
+
+        let mut lock = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|error| format!("Unable to lock ref {}: {}", update.name, error))?;
+
+        if let Some(new_value) = &update.new_value {
+            lock.write_all(format!("{}\n", new_value).as_bytes())
+                .map_err(|error| error.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn check_expected(&self, update: &RefUpdate) -> Result<(), String> {
+        let current = self.read(&update.name);
+
+        let matches = match &update.expected {
+            RefExpectation::Any => true,
+            RefExpectation::Missing => current.is_none(),
+            RefExpectation::Value(expected) => current.as_ref() == Some(expected),
+        };
+
+        if !matches {
+            return Err(format!(
+                "Ref {} is at {}, but expected {}",
+                update.name,
+                current.as_deref().unwrap_or("nothing"),
+                match &update.expected {
+                    RefExpectation::Value(expected) => expected,
+                    _ => "nothing",
+                }
+            ));
+        }
+
+        if update.new_value.is_none() && current.is_none() {
+            return Err(format!(
+                "Could not delete ref {}, it does not exist",
+                update.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn apply(&self, update: &RefUpdate) -> Result<(), String> {
+        let lock_path = self.lock_path(&update.name);
+        let path = self.ref_path(&update.name);
+
+        let result = match update.new_value {
+            Some(_) => rename_replace(&lock_path, &path),
+            None => remove_file(&path).and_then(|()| remove_file(&lock_path)),
+        };
+
+        result.map_err(|error| format!("Could not update ref {}: {}", update.name, error))
+    }
+}
+
+impl RefStore for FilesRefStore {
+    fn read(&self, name: &str) -> Option<String> {
+        self.read_with_depth(name, 0)
+    }
+
+    fn list(&self, prefix: &str) -> Vec<String> {
+        let mut refs = vec![];
+
+        if validate_ref_name(prefix).is_ok() {
+            list_refs_in_path(&self.ref_path(prefix), prefix, &mut refs);
+        }
+
+        refs.sort();
+
+        refs
+    }
+
+    // Every ref is locked and checked before any is touched, so a failure up to
+    // that point leaves all refs as they were. Only an I/O error while renaming
+    // the lock files into place can leave the transaction half applied.
+    fn commit(&self, transaction: &RefTransaction) -> Result<(), String> {
+        transaction.validate()?;
+
+        let mut updates = transaction.updates().to_vec();
+        // Locking in a fixed order avoids two transactions deadlocking on each
+        // other's locks.
+        updates.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut locked = vec![];
+
+        let prepared = updates.iter().try_for_each(|update| {
+            self.lock(update)?;
+            locked.push(update);
+
+            self.check_expected(update)
+        });
+
+        if let Err(error) = prepared {
+            for update in locked {
+                let _ = remove_file(self.lock_path(&update.name));
+            }
+
+            return Err(error);
+        }
+
+        for (index, update) in updates.iter().enumerate() {
+            if let Err(error) = self.apply(update) {
+                for update in &updates[index..] {
+                    let _ = remove_file(self.lock_path(&update.name));
+                }
+
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Repository {
+    pub fn refs(&self) -> Box<dyn RefStore> {
+        Box::new(FilesRefStore::new(self.repo_path("")))
+    }
+
+    pub fn ref_read(&self, name: &str) -> Option<String> {
+        self.refs().read(name)
+    }
+
+    pub fn ref_write(&self, name: &str, sha: &str) -> Result<(), String> {
+        self.refs()
+            .commit(RefTransaction::new().update(name, sha, RefExpectation::Any))
+    }
+
+    pub fn ref_delete(&self, name: &str) -> Result<(), String> {
+        self.refs()
+            .commit(RefTransaction::new().delete(name, RefExpectation::Any))
+    }
+
+    pub fn ref_list(&self, prefix: &str) -> Vec<String> {
+        self.refs().list(prefix)
+    }
+
+    // Points HEAD to a branch (e.g. "refs/heads/main"). HEAD is locked while
+    // it is written, like any ref.
+    pub fn head_attach(&self, branch: &str) -> Result<(), String> {
+        validate_ref_name(branch)?;
+
+        let lock_path = self.repo_path("HEAD.lock");
+        let result = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .and_then(|mut lock| lock.write_all(format!("ref: {}\n", branch).as_bytes()));
+
+        if let Err(error) = result {
+            if error.kind() != io::ErrorKind::AlreadyExists {
+                let _ = remove_file(&lock_path);
+            }
+
+            return Err(format!("Unable to lock ref HEAD: {}", error));
+        }
+
+        rename_replace(&lock_path, &self.repo_path("HEAD"))
+            .map_err(|error| format!("Could not update ref HEAD: {}", error))
+    }
+
+    // Points HEAD directly to a commit, detaching it from any branch.
+    pub fn head_detach(&self, sha: &str) -> Result<(), String> {
+        self.ref_write("HEAD", sha)
+    }
+
+    // Returns the branch HEAD points to (e.g. "refs/heads/main"), even if it
+    // has no commits yet, or None if HEAD is detached. Commands should use it
+    // instead of assuming a branch name, as it depends on init.defaultBranch.
+    pub fn head_branch(&self) -> Option<String> {
+        let content = read_to_string(self.repo_path("HEAD")).ok()?;
+
+        content
+            .trim()
+            .strip_prefix("ref: ")
+            .filter(|target| target.starts_with("refs/heads/"))
+            .map(str::to_owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use super::*;
+    use crate::testing::TestRepository;
+
+    const A: &str = "1111111111111111111111111111111111111111";
+    const B: &str = "2222222222222222222222222222222222222222";
+    const C: &str = "3333333333333333333333333333333333333333";
+
+    // Returns the lock files left anywhere in a directory.
+    fn lock_files(path: &Path) -> Vec<PathBuf> {
+        let mut locks = vec![];
+
+        for entry in read_dir(path).unwrap().flatten() {
+            if entry.path().is_dir() {
+                locks.extend(lock_files(&entry.path()));
+            } else if entry.file_name().to_string_lossy().ends_with(".lock") {
+                locks.push(entry.path());
+            }
+        }
+
+        locks
+    }
+
+    // A repository with refs/heads/a at A and refs/heads/b at B.
+    fn two_branches(name: &str) -> TestRepository {
+        let test = TestRepository::new(name);
+
+        test.repo.ref_write("refs/heads/a", A).unwrap();
+        test.repo.ref_write("refs/heads/b", B).unwrap();
+
+        test
+    }
+
+    fn assert_unchanged(test: &TestRepository) {
+        assert_eq!(test.repo.ref_read("refs/heads/a").as_deref(), Some(A));
+        assert_eq!(test.repo.ref_read("refs/heads/b").as_deref(), Some(B));
+        assert_eq!(test.repo.ref_read("refs/heads/c"), None);
+    }
+
+    #[test]
+    fn transaction_applies_every_update() {
+        let test = two_branches("transaction_applies_every_update");
+
+        test.repo
+            .refs()
+            .commit(
+                RefTransaction::new()
+                    .update("refs/heads/a", C, RefExpectation::Value(A.to_owned()))
+                    .delete("refs/heads/b", RefExpectation::Value(B.to_owned()))
+                    .update("refs/heads/c", A, RefExpectation::Missing),
+            )
+            .unwrap();
+
+        assert_eq!(test.repo.ref_read("refs/heads/a").as_deref(), Some(C));
+        assert_eq!(test.repo.ref_read("refs/heads/b"), None);
+        assert_eq!(test.repo.ref_read("refs/heads/c").as_deref(), Some(A));
+        assert_eq!(
+            test.repo.ref_list("refs/heads"),
+            ["refs/heads/a", "refs/heads/c"]
+        );
+        assert_eq!(lock_files(&test.repo.repo_path("")), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn wrong_expected_value_changes_nothing() {
+        let test = two_branches("wrong_expected_value_changes_nothing");
+
+        for expected in [RefExpectation::Value(C.to_owned()), RefExpectation::Missing] {
+            let error = test
+                .repo
+                .refs()
+                .commit(
+                    RefTransaction::new()
+                        .update("refs/heads/a", C, RefExpectation::Any)
+                        .delete("refs/heads/c", RefExpectation::Any)
+                        .update("refs/heads/b", C, expected),
+                )
+                .unwrap_err();
+
+            assert!(error.contains("refs/heads"), "{}", error);
+            assert_unchanged(&test);
+            assert_eq!(lock_files(&test.repo.repo_path("")), Vec::<PathBuf>::new());
+        }
+    }
+
+    #[test]
+    fn deleting_a_missing_ref_changes_nothing() {
+        let test = two_branches("deleting_a_missing_ref_changes_nothing");
+
+        let error = test
+            .repo
+            .refs()
+            .commit(
+                RefTransaction::new()
+                    .update("refs/heads/a", C, RefExpectation::Any)
+                    .delete("refs/heads/c", RefExpectation::Any),
+            )
+            .unwrap_err();
+
+        assert!(error.contains("does not exist"), "{}", error);
+        assert_unchanged(&test);
+        assert_eq!(lock_files(&test.repo.repo_path("")), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn ref_locked_by_someone_else_fails_the_transaction() {
+        let test = two_branches("ref_locked_by_someone_else");
+        let lock = test.repo.repo_path("refs/heads/b.lock");
+        write(&lock, "someone else\n").unwrap();
+
+        let error = test
+            .repo
+            .refs()
+            .commit(
+                RefTransaction::new()
+                    .update("refs/heads/a", C, RefExpectation::Any)
+                    .update("refs/heads/b", C, RefExpectation::Any)
+                    .update("refs/heads/c", C, RefExpectation::Any),
+            )
+            .unwrap_err();
+
+        assert!(
+            error.contains("Unable to lock ref refs/heads/b"),
+            "{}",
+            error
+        );
+        assert_unchanged(&test);
+        // The other writer's lock is theirs to remove.
+        assert_eq!(
+            lock_files(&test.repo.repo_path("")),
+            std::slice::from_ref(&lock)
+        );
+        assert_eq!(read_to_string(&lock).unwrap(), "someone else\n");
+    }
+
+    #[test]
+    fn same_ref_twice_is_rejected() {
+        let test = two_branches("same_ref_twice_is_rejected");
+
+        let error = test
+            .repo
+            .refs()
+            .commit(
+                RefTransaction::new()
+                    .update("refs/heads/a", C, RefExpectation::Any)
+                    .delete("refs/heads/a", RefExpectation::Any),
+            )
+            .unwrap_err();
+
+        assert!(error.contains("updated twice"), "{}", error);
+        assert_unchanged(&test);
+        assert_eq!(lock_files(&test.repo.repo_path("")), Vec::<PathBuf>::new());
+    }
+}
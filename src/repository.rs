@@ -1,19 +1,92 @@
-use super::Serialise;
 use std::{
     env,
-    fs::{canonicalize, create_dir_all, metadata, read, read_dir, read_to_string, write, File},
+    error::Error,
+    fmt,
+    fs::{
+        canonicalize, copy, create_dir_all, metadata, read_dir, read_to_string, remove_dir,
+        remove_file, write, File, OpenOptions,
+    },
     io::{self, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process,
     str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
 };
 
 use configparser::ini::Ini;
 use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
-
-use super::git_object::GitObject;
+use sha1::{Digest, Sha1};
+use unicode_normalization::UnicodeNormalization;
+
+use super::{
+    config::global_config_get,
+    git_object::{kvlm_parser, GitObject},
+    hooks::Listener,
+    object::serialise::Serialise,
+    platform::rename_replace,
+    validate::{is_contained_path, validate_object_id, validate_ref_name},
+};
 
 const GOT_DIR: &str = ".got";
 
+// Replacement chains longer than this are considered a loop.
+const MAX_REPLACE_DEPTH: usize = 5;
+
+// Tags pointing to tags deeper than this are considered a loop.
+pub(crate) const MAX_PEEL_DEPTH: usize = 10;
+
+// Largest object read unless core.maxObjectSize says otherwise. Objects are
+// read whole into memory, so without a limit a small compressed file declaring
+// (or inflating to) a huge size could exhaust it.
+const DEFAULT_MAX_OBJECT_SIZE: u64 = 1 << 30;
+
+// Longest valid object header: the longest type name, a space, the digits of
+// the largest u64 size.
+const MAX_OBJECT_HEADER_LENGTH: usize = 6 + 1 + 20;
+
+// Prefix of the temporary files objects are written to before being renamed
+// into place.
+const TMP_OBJECT_PREFIX: &str = "tmp_obj_";
+
+// Creates a new temporary object file in a directory, with a name no other
+// writer (in this or another process) is using.
+fn create_tmp_object(directory: &Path) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let path = directory.join(format!(
+            "{}{}_{}",
+            TMP_OBJECT_PREFIX,
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Parses a size with an optional k, m or g suffix, like git's integer options.
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_ascii_lowercase();
+
+    let (digits, multiplier) = match value.as_bytes().last()? {
+        b'k' => (&value[..value.len() - 1], 1 << 10),
+        b'm' => (&value[..value.len() - 1], 1 << 20),
+        b'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (&value[..], 1),
+    };
+
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
 fn list_files_in_path(path: &PathBuf, paths_to_ignore: &Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
     let mut files = vec![];
     let entries = read_dir(path).expect("Could not read entries from this dir.");
@@ -38,62 +111,262 @@ fn list_files_in_path(path: &PathBuf, paths_to_ignore: &Vec<PathBuf>) -> io::Res
     Ok(files)
 }
 
+// Checks that we know how to read a repository with this configuration. Later
+// format versions, or extensions we do not support, would change how data is
+// stored, and reading it as if they were not there could corrupt it.
+//
+// Version 0 repositories ignore unknown extensions (they predate them), but
+// extensions only valid for version 1 are rejected, as git does.
+fn check_repository_format(config: &Ini) -> Result<(), String> {
+    let version = match config.get("core", "repositoryformatversion") {
+        Some(version) => version
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid repository format version {:?}", version))?,
+        None => 0,
+    };
+
+    if version > 1 {
+        return Err(format!(
+            "Expected repository format version 0 or 1, found {}",
+            version
+        ));
+    }
+
+    let extensions = match config.get_map_ref().get("extensions") {
+        Some(extensions) => extensions,
+        None => return Ok(()),
+    };
+
+    for (extension, value) in extensions {
+        let value = value.as_deref().unwrap_or("");
+
+        let supported = match extension.as_str() {
+            // Does nothing, it only exists to test extension handling.
+            "noop" => true,
+            // Also accepted in version 0, as git did before version 1. We
+            // never prune objects, and report missing ones as missing.
+            "worktreeconfig" | "preciousobjects" | "partialclone" => true,
+            "objectformat" if version == 1 => value.eq_ignore_ascii_case("sha1"),
+            "refstorage" if version == 1 => value.eq_ignore_ascii_case("files"),
+            "objectformat" | "refstorage" => {
+                return Err(format!(
+                    "Repository format version is 0, but version 1 extension {} is set",
+                    extension
+                ))
+            }
+            _ if version == 0 => true,
+            _ => return Err(format!("Unknown repository extension: {}", extension)),
+        };
+
+        if !supported {
+            return Err(format!(
+                "Unsupported value for repository extension {}: {}",
+                extension, value
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Copies the contents of a template directory (hook samples, info/exclude, a
+// description...) into a new .got directory, keeping files that already exist.
+// A config file in the template is skipped, as init writes its own.
+fn copy_template(template: &Path, gotdir: &Path) -> io::Result<()> {
+    create_dir_all(gotdir)?;
+
+    for entry in read_dir(template)? {
+        let entry = entry?;
+        let target = gotdir.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_template(&entry.path(), &target)?;
+        } else if entry.file_name() != "config" && !target.exists() {
+            copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Why a repository could not be opened.
+#[derive(Debug)]
+pub enum OpenError {
+    // No repository in the given directory or any of its parents.
+    NotFound(PathBuf),
+    // The directory has no .got directory.
+    NotARepository(PathBuf),
+    MissingConfig(PathBuf),
+    InvalidConfig(PathBuf, String),
+    // The format version or an extension is not supported.
+    UnsupportedFormat(String),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::NotFound(path) => {
+                write!(
+                    f,
+                    "Not a got repository (or any of the parent directories): {:?}",
+                    path
+                )
+            }
+            OpenError::NotARepository(path) => write!(f, "Not a valid Got repository: {:?}", path),
+            OpenError::MissingConfig(path) => write!(f, "Configuration file not found: {:?}", path),
+            OpenError::InvalidConfig(path, error) => {
+                write!(
+                    f,
+                    "Configuration file {:?} has wrong format: {}",
+                    path, error
+                )
+            }
+            OpenError::UnsupportedFormat(error) => write!(f, "{}", error),
+            OpenError::Io(path, error) => write!(f, "Could not access {:?}: {}", path, error),
+        }
+    }
+}
+
+impl Error for OpenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OpenError::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Options for creating a repository, as given to `got init`.
+#[derive(Default)]
+pub struct InitOptions {
+    // Directory whose contents are copied into the new .got directory. Falls
+    // back to $GOT_TEMPLATE_DIR and then to init.templateDir.
+    pub template: Option<PathBuf>,
+    // Branch HEAD points to, init.defaultBranch if not given.
+    pub initial_branch: Option<String>,
+}
+
+// A Repository can be shared between threads (e.g. behind an Arc) by tools
+// working on it concurrently. Everything it holds is read-only once opened,
+// except the event listeners, which are behind a lock, and the state that
+// changes lives on disk, where refs are updated through lock files. Any cache
+// added here needs its own locking to keep it Send + Sync, which is checked
+// below.
 pub struct Repository {
     worktree: PathBuf,
     gotdir: PathBuf,
     ignore: Vec<PathBuf>,
+    replace_objects: bool,
+    config: Ini,
+    pub(crate) listeners: Mutex<Vec<Listener>>,
 }
 
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Repository>();
+};
+
 impl Repository {
     // Returns a new Repository.
     // path is the system absolute path for this repository.
     // force ensures that a new Repository is created even if gotdir (.got)
     // does not exist. Useful for creating new repositories.
-    pub fn new(path: PathBuf, force: bool) -> Self {
-        // 1. Panic if <path>/.got does not exist and force is false
-        // 2. Read configuration file from /.got/config, panic if missing and force is false
-        // 3. Read repositoryformatversion from config
+    pub fn new(path: PathBuf, force: bool) -> Result<Self, OpenError> {
+        Repository::load(path, force)
+    }
+
+    // Opens the repository whose worktree is at path.
+    pub fn open(path: PathBuf) -> Result<Self, OpenError> {
+        Repository::load(path, false)
+    }
+
+    // Opens the repository containing path, looking in path and then in each of
+    // its parents.
+    pub fn discover(path: PathBuf) -> Result<Self, OpenError> {
+        let canonical_path =
+            canonicalize(&path).map_err(|error| OpenError::Io(path.clone(), error))?;
+
+        match canonical_path
+            .ancestors()
+            .find(|ancestor| ancestor.join(GOT_DIR).is_dir())
+        {
+            Some(worktree) => Repository::open(worktree.to_path_buf()),
+            None => Err(OpenError::NotFound(path)),
+        }
+    }
+
+    fn load(path: PathBuf, force: bool) -> Result<Self, OpenError> {
+        // 1. Fail if <path>/.got does not exist and force is false
+        // 2. Read configuration file from /.got/config, fail if missing and force is false
+        // 3. Check repositoryformatversion and extensions from config
         let gotdir = path.join(GOT_DIR);
 
         if !gotdir.is_dir() && !force {
-            panic!("Not a valid Got repository");
+            return Err(OpenError::NotARepository(path));
         }
 
         let config_file_path = gotdir.join("config");
         let mut config_parser = Ini::new();
 
-        let config = match read_to_string(config_file_path) {
+        let config = match read_to_string(&config_file_path) {
             Ok(config_content) => Some(
                 config_parser
                     .read(config_content)
-                    .expect("Configuration file has wrong format"),
+                    .map_err(|error| OpenError::InvalidConfig(config_file_path.clone(), error))?,
             ),
             Err(_) => None,
         };
 
         if config.is_none() && !force {
-            panic!("Configuration file not found");
+            return Err(OpenError::MissingConfig(config_file_path));
+        }
+
+        if config.is_some() {
+            check_repository_format(&config_parser).map_err(OpenError::UnsupportedFormat)?;
+
+            // With extensions.worktreeConfig, settings in config.worktree
+            // override the shared ones.
+            if config_parser
+                .getboolcoerce("extensions", "worktreeconfig")
+                .ok()
+                .flatten()
+                .unwrap_or(false)
+            {
+                let worktree_config_path = gotdir.join("config.worktree");
+
+                if let Ok(worktree_config) = read_to_string(&worktree_config_path) {
+                    config_parser
+                        .read_and_append(worktree_config)
+                        .map_err(|error| OpenError::InvalidConfig(worktree_config_path, error))?;
+                }
+            }
         }
 
-        // Read paths to ignore from .gitignore
+        // Read paths to ignore from .gitignore, relative to the worktree
+        // wherever got is run from. Empty lines would ignore the whole worktree.
         let paths_to_ignore: Vec<PathBuf> = match read_to_string(path.join(".gitignore")) {
             Ok(content) => content
                 .split("\n")
-                .map(|split| PathBuf::from(split))
-                .map(|path| {
-                    let mut absolute_path = env::current_dir().unwrap();
-                    absolute_path.push(path);
-                    absolute_path
-                })
+                .filter(|line| !line.is_empty())
+                .map(|line| path.join(line))
                 .collect(),
             Err(_) => vec![],
         };
 
-        return Repository {
+        // Objects are substituted by their refs/replace/ counterparts unless
+        // explicitly disabled (this is what --no-replace-objects sets).
+        let replace_objects = env::var_os("GOT_NO_REPLACE_OBJECTS").is_none();
+
+        Ok(Repository {
             worktree: path,
             gotdir,
             ignore: paths_to_ignore,
-        };
+            replace_objects,
+            config: config_parser,
+            listeners: Mutex::new(vec![]),
+        })
     }
 
     pub fn generate_default_config() -> Ini {
@@ -103,10 +376,26 @@ impl Repository {
         config.set("core", "filemode", Some(String::from("false")));
         config.set("core", "bare", Some(String::from("false")));
 
-        return config;
+        config
+    }
+
+    // Name of the branch new repositories start on: init.defaultBranch from the
+    // global configuration, or master.
+    pub fn default_branch_name() -> Result<String, String> {
+        let name =
+            global_config_get("init", "defaultbranch")?.unwrap_or_else(|| String::from("master"));
+
+        validate_ref_name(&format!("refs/heads/{}", name))
+            .map_err(|_| format!("Invalid init.defaultBranch: {:?}", name))?;
+
+        Ok(name)
     }
 
     pub fn create(path: PathBuf) -> Result<Self, String> {
+        Repository::init(path, &InitOptions::default())
+    }
+
+    pub fn init(path: PathBuf, options: &InitOptions) -> Result<Self, String> {
         // Does the path exist and it is a dir? Create subdirs and return Repo
         // Does the path exist and it is not a dir? Return Error
         // Does the path not exist? Create the path
@@ -124,57 +413,170 @@ impl Repository {
             path
         };
 
-        let repo = Repository::new(working_dir, true);
+        let initial_branch = match &options.initial_branch {
+            Some(initial_branch) => initial_branch.clone(),
+            None => Repository::default_branch_name()?,
+        };
+        validate_ref_name(&format!("refs/heads/{}", initial_branch))
+            .map_err(|_| format!("Invalid initial branch name: {:?}", initial_branch))?;
+
+        let template = match &options.template {
+            Some(template) => Some(template.clone()),
+            None => match env::var_os("GOT_TEMPLATE_DIR") {
+                Some(template) => Some(PathBuf::from(template)),
+                None => global_config_get("init", "templatedir")?.map(PathBuf::from),
+            },
+        };
+
+        let repo = Repository::load(working_dir, true).map_err(|error| error.to_string())?;
+
+        // An empty template path (--template=) means no template at all.
+        if let Some(template) = template.filter(|template| !template.as_os_str().is_empty()) {
+            copy_template(&template, &repo.gotdir).map_err(|error| {
+                format!("Could not copy template from {:?}: {}", template, error)
+            })?;
+        }
 
         repo.repo_dir("branches", true);
         repo.repo_dir("objects", true);
         repo.repo_dir("refs/tags", true);
         repo.repo_dir("refs/heads", true);
 
-        // Create description file
-        write(
-            repo.repo_file("description", false),
-            "Unnamed repository; edit this file 'description' to name the repository.\n",
-        )
-        .expect("Could not write description file");
+        // Create description file, unless the template provided one
+        let description_path = repo.repo_file("description", false);
+        if !description_path.exists() {
+            write(
+                description_path,
+                "Unnamed repository; edit this file 'description' to name the repository.\n",
+            )
+            .expect("Could not write description file");
+        }
 
         // Write HEAD file
-        write(repo.repo_file("HEAD", false), "ref: refs/heads/master\n")
-            .expect("Could not write HEAD file");
+        write(
+            repo.repo_file("HEAD", false),
+            format!("ref: refs/heads/{}\n", initial_branch),
+        )
+        .expect("Could not write HEAD file");
 
         // Write configuration file
-        let config = Repository::generate_default_config();
+        let mut config = Repository::generate_default_config();
         config
             .write(repo.repo_file("config", false))
             .expect("Could not write configuration file on repo creation");
 
-        Ok(repo)
+        // If the config file can be found with a different case, we are on a
+        // case-insensitive filesystem (the default on macOS and Windows), and
+        // paths differing only in case refer to the same file.
+        if repo.repo_path("CoNfIg").exists() {
+            config.set("core", "ignorecase", Some(String::from("true")));
+            config
+                .write(repo.repo_file("config", false))
+                .expect("Could not write configuration file on repo creation");
+        }
+
+        Repository::open(repo.worktree).map_err(|error| error.to_string())
+    }
+
+    // Returns a value from the repository configuration. Section and key
+    // names are case insensitive.
+    pub fn config_get(&self, section: &str, key: &str) -> Option<String> {
+        self.config.get(section, key)
+    }
+
+    // Returns the size of the largest object that can be read.
+    pub fn max_object_size(&self) -> u64 {
+        self.config_get("core", "maxobjectsize")
+            .and_then(|value| parse_size(&value))
+            .unwrap_or(DEFAULT_MAX_OBJECT_SIZE)
+    }
+
+    // Returns true if objects should be hashed when read, to detect damaged
+    // files (core.checksumObjects, on unless set to false). Turning it off
+    // makes reading large objects faster.
+    pub fn checksum_objects(&self) -> bool {
+        self.config
+            .getbool("core", "checksumobjects")
+            .ok()
+            .flatten()
+            .unwrap_or(true)
     }
 
+    // Returns true if paths differing only in case should be treated as the
+    // same path (core.ignorecase, detected on init).
+    pub fn ignore_case(&self) -> bool {
+        self.config
+            .getbool("core", "ignorecase")
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    pub fn worktree(&self) -> &PathBuf {
+        &self.worktree
+    }
+
+    // Lists every file in the worktree, skipping ignored paths and the .got
+    // directory itself. With core.precomposeunicode set, paths are returned in
+    // NFC form, as macOS hands out decomposed (NFD) file names.
     pub fn list_files(&self) -> io::Result<Vec<PathBuf>> {
-        list_files_in_path(&self.worktree, &self.ignore)
+        let mut paths_to_ignore = self.ignore.clone();
+        paths_to_ignore.push(self.gotdir.clone());
+
+        let files = list_files_in_path(&self.worktree, &paths_to_ignore)?;
+
+        if !self.precompose_unicode() {
+            return Ok(files);
+        }
+
+        Ok(files
+            .into_iter()
+            .map(|path| self.precompose_path(path))
+            .collect())
+    }
+
+    fn precompose_unicode(&self) -> bool {
+        self.config
+            .getbool("core", "precomposeunicode")
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    // Normalises the worktree relative part of a path to NFC.
+    fn precompose_path(&self, path: PathBuf) -> PathBuf {
+        match path
+            .strip_prefix(&self.worktree)
+            .ok()
+            .and_then(|relative| relative.to_str())
+        {
+            Some(relative) => self.worktree.join(relative.nfc().collect::<String>()),
+            None => path,
+        }
     }
 
     // From current repository, return a parent directory that is an active repository.
     // We identify an active repository because it contains a ".got" directory.
     // Useful when we want to execute commands when inside child directories.
-    pub fn repo_find(path: PathBuf) -> Option<Self> {
-        let canonical_path = canonicalize(&path).expect("Could not convert path to canonical");
-        let parent_path = canonical_path.parent();
-        let maybe_got_path = canonical_path.join(GOT_DIR).is_dir();
-
-        if parent_path.is_none() {
-            return None;
-        } else if maybe_got_path {
-            return Some(Repository::new(canonical_path, false));
-        } else {
-            return Repository::repo_find(parent_path.unwrap().to_owned());
+    // Returns None if there is no repository, and an error if one is found but
+    // cannot be opened.
+    pub fn repo_find(path: PathBuf) -> Result<Option<Self>, OpenError> {
+        match Repository::discover(path) {
+            Ok(repo) => Ok(Some(repo)),
+            Err(OpenError::NotFound(_)) => Ok(None),
+            Err(error) => Err(error),
         }
     }
 
-    // Returns a new path that is relative to .got dir
+    // Returns a new path that is relative to .got dir. Panics if the path would
+    // escape the .got dir (absolute paths or ".." components).
     pub fn repo_path(&self, rel_path_str: &str) -> PathBuf {
         let rel_path = PathBuf::from_str(rel_path_str).expect("Invalid path");
+
+        if !is_contained_path(&rel_path) {
+            panic!("Path {:?} is outside of the repository", rel_path_str);
+        }
+
         self.gotdir.join(rel_path)
     }
 
@@ -183,8 +585,8 @@ impl Repository {
     pub fn repo_file(&self, rel_path_str: &str, should_create_dir: bool) -> PathBuf {
         let path = self.repo_path(rel_path_str);
 
-        if !path.parent().is_none() {
-            return path;
+        if path.parent().is_some() {
+            path
         } else {
             let mut dir_path = path.clone();
             dir_path.pop();
@@ -192,7 +594,7 @@ impl Repository {
             if self.repo_dir(rel_path_str, should_create_dir).is_none() {
                 panic!("Could not create file because path does not exist");
             } else {
-                return path;
+                path
             }
         }
     }
@@ -204,7 +606,7 @@ impl Repository {
 
         if path.exists() {
             if path.is_dir() {
-                return Some(path);
+                Some(path)
             } else {
                 panic!("Provided path is not a directory")
             }
@@ -215,13 +617,54 @@ impl Repository {
                     Err(error) => panic!("Could not create directory: {:?}", error),
                 }
             } else {
-                return None;
+                None
             }
         }
     }
 
-    pub fn _object_find(&self, name: &str, _format: &str, _follow: bool) -> String {
-        return name.to_owned();
+    // Resolves a name (a full object id, HEAD, or a ref, with or without its
+    // refs/, refs/heads/ or refs/tags/ prefix) into an object id. With an object
+    // type, tags are peeled and commits are followed to their tree until an
+    // object of that type is found.
+    pub fn object_find(&self, name: &str, object_type: Option<&str>) -> Result<String, String> {
+        let mut sha = if validate_object_id(name).is_ok() {
+            name.to_owned()
+        } else {
+            [
+                name.to_owned(),
+                format!("refs/{}", name),
+                format!("refs/tags/{}", name),
+                format!("refs/heads/{}", name),
+            ]
+            .iter()
+            .find_map(|candidate| self.ref_read(candidate))
+            .ok_or_else(|| format!("Not a valid object name {}", name))?
+        };
+
+        let object_type = match object_type {
+            Some(object_type) => object_type,
+            None => return Ok(sha),
+        };
+
+        for _ in 0..MAX_PEEL_DEPTH {
+            let (actual_type, content) = self.object_read_raw(&sha)?;
+
+            let next_header = match actual_type.as_str() {
+                actual_type if actual_type == object_type => return Ok(sha),
+                "tag" => "object",
+                "commit" if object_type == "tree" => "tree",
+                _ => break,
+            };
+
+            let content = String::from_utf8_lossy(&content);
+            sha = kvlm_parser(&content)?
+                .into_iter()
+                .find(|(key, _)| *key == next_header)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("Object {} has no {} header", sha, next_header))?;
+        }
+
+        Err(format!("{} does not name a {}", name, object_type))
     }
 
     pub fn object_write(&self, object: &impl Serialise, actually_write: bool) -> String {
@@ -234,59 +677,320 @@ impl Repository {
             let file_path =
                 self.repo_file(&format!("objects/{}/{}", folder_name, filename)[..], true);
 
-            // According to docs, "Depending on the platform, this function may fail if the full directory path does not exist."
-            // we create directories, just in case.
-            create_dir_all(file_path.parent().unwrap()).expect("Could not create directory.");
-            let file_writer = File::create(file_path).expect("Could not create file.");
+            // Objects never change, so one that is already there is kept.
+            if !file_path.is_file() {
+                let directory = file_path.parent().unwrap();
+                create_dir_all(directory).expect("Could not create directory.");
+
+                // The object is written to a temporary file and renamed into
+                // place, so an interrupted write leaves a stray tmp_obj_ file
+                // (see object_sweep_temporaries) instead of a truncated object.
+                let (tmp_path, tmp_file) =
+                    create_tmp_object(directory).expect("Could not create file.");
+
+                let mut file_contents_encoder = ZlibEncoder::new(tmp_file, Compression::fast());
+                file_contents_encoder
+                    .write_all(&object.content_with_headers())
+                    .expect("Could not compress object contents.");
+                file_contents_encoder
+                    .finish()
+                    .expect("Could not compress object contents.");
+
+                rename_replace(&tmp_path, &file_path).expect("Could not move object into place.");
+            }
+        }
+
+        hash.to_owned()
+    }
+
+    // Removes the temporary files left in the object store by interrupted
+    // writes, and the fan-out directories left empty. Only files older than
+    // expiry are removed, as younger ones may belong to a write in progress.
+    // Returns how many files were removed.
+    pub fn object_sweep_temporaries(&self, expiry: Duration) -> io::Result<usize> {
+        let mut removed = 0;
+
+        for entry in read_dir(self.repo_path("objects"))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if name.len() != 2
+                || !name.bytes().all(|byte| byte.is_ascii_hexdigit())
+                || !entry.file_type()?.is_dir()
+            {
+                continue;
+            }
+
+            for file in read_dir(entry.path())? {
+                let file = file?;
 
-            let mut file_contents_encoder = ZlibEncoder::new(file_writer, Compression::fast());
-            file_contents_encoder
-                .write(object.content_with_headers().as_bytes())
-                .expect("Could not compress object contents.");
+                if !file
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(TMP_OBJECT_PREFIX)
+                {
+                    continue;
+                }
+
+                let age = file
+                    .metadata()?
+                    .modified()?
+                    .elapsed()
+                    .unwrap_or(Duration::ZERO);
+
+                if age >= expiry {
+                    remove_file(file.path())?;
+                    removed += 1;
+                }
+            }
+
+            // Fails if the directory still has files, which is fine.
+            let _ = remove_dir(entry.path());
         }
 
-        return hash.to_owned();
+        Ok(removed)
     }
 
-    // Returns object associated to a given hash.
-    pub fn object_read(&self, sha: &str) -> Result<GitObject, &'static str> {
+    pub fn object_exists(&self, sha: &str) -> bool {
+        validate_object_id(sha).is_ok()
+            && self
+                .repo_path(&format!("objects/{}/{}", &sha[..2], &sha[2..]))
+                .is_file()
+    }
+
+    // Returns the object that should be read in place of the given one, following
+    // refs/replace/<sha> if replace objects are enabled.
+    pub fn object_replacement(&self, sha: &str) -> Result<String, &'static str> {
+        let mut current = sha.to_owned();
+
+        if !self.replace_objects {
+            return Ok(current);
+        }
+
+        for _ in 0..MAX_REPLACE_DEPTH {
+            match self.ref_read(&format!("refs/replace/{}", current)) {
+                Some(replacement) => current = replacement,
+                None => return Ok(current),
+            }
+        }
+
+        Err("Replace depth too high, there may be a replacement loop")
+    }
+
+    // Returns the type and content of the object associated to a given hash, or
+    // of its replacement if there is one.
+    //
+    // Errors about damaged files (which cannot be decompressed, lie about their
+    // size or do not hash to their id) name the file, so it can be restored.
+    pub fn object_read_raw(&self, sha: &str) -> Result<(String, Vec<u8>), String> {
+        validate_object_id(sha)?;
+
+        let replaced_sha = self.object_replacement(sha)?;
+        let sha = replaced_sha.as_str();
+        validate_object_id(sha)?;
+
         let file_relative_path = format!("objects/{}/{}", &sha[..2], &sha[2..]);
         let file_relative_path_str = file_relative_path.as_str();
         let file_path = self.repo_file(file_relative_path_str, false);
 
-        let compressed_file_contents = read(file_path).expect("File does not exist");
-        let mut file_contents_decoder = ZlibDecoder::new(&compressed_file_contents[..]);
-        let mut file_contents = String::new();
-        file_contents_decoder
-            .read_to_string(&mut file_contents)
-            .unwrap();
-
-        let object_type_index = match file_contents.find(' ') {
-            Some(index) => index,
-            None => return Err("File is malformed"),
+        let file = File::open(&file_path).map_err(|_| "Object does not exist")?;
+        let corrupt = |problem: &str| {
+            format!(
+                "Object file {} is corrupt: {}",
+                file_path.display(),
+                problem
+            )
         };
-        let object_type = &file_contents[0..object_type_index];
+        let mut decoder = ZlibDecoder::new(file);
+
+        // The header is read on its own first, so the declared size can be
+        // checked before decompressing anything else.
+        let mut header = vec![];
+        let mut byte = [0u8; 1];
+
+        loop {
+            if header.len() > MAX_OBJECT_HEADER_LENGTH {
+                return Err(corrupt("header is too long"));
+            }
 
-        let object_size_index = match file_contents.find('\x00') {
-            Some(index) => index,
-            None => return Err("File is malformed"),
+            match decoder.read(&mut byte) {
+                Ok(1) if byte[0] == b'\x00' => break,
+                Ok(1) => header.push(byte[0]),
+                Ok(_) => return Err(corrupt("header is truncated")),
+                Err(error) => return Err(corrupt(&error.to_string())),
+            }
+        }
+
+        let (object_type, object_size) = match header.iter().position(|byte| *byte == b' ') {
+            Some(index) => (&header[..index], &header[index + 1..]),
+            None => return Err(corrupt("header has no size")),
         };
-        let object_size = &file_contents[object_type_index + 1..object_size_index];
-        let object_content = &file_contents[object_size_index..];
-        let real_object_size = object_content.len() - 1;
 
-        if object_size.parse::<usize>().unwrap() != real_object_size {
-            return Err("Could not read object because sizes mismatch (object is malformed).");
-        } else {
-            let content = object_content.to_string();
-
-            match object_type {
-                "commit" => Ok(GitObject::Commit(content)),
-                "tree" => Ok(GitObject::Tree(content)),
-                "tag" => Ok(GitObject::Tag(content)),
-                "blob" => Ok(GitObject::Blob(content)),
-                _ => Err("Object type does not match any known types."),
+        if object_type.is_empty()
+            || object_size.is_empty()
+            || !object_size.iter().all(|byte| byte.is_ascii_digit())
+        {
+            return Err(corrupt("header is malformed"));
+        }
+
+        let object_size = String::from_utf8_lossy(object_size)
+            .parse::<u64>()
+            .map_err(|_| corrupt("header is malformed"))?;
+
+        if object_size > self.max_object_size() {
+            return Err(format!(
+                "Object {} of {} bytes is larger than core.maxObjectSize",
+                sha, object_size
+            ));
+        }
+
+        // The declared size is not trusted for allocating: reading at most one
+        // byte past it is enough to tell it was a lie.
+        let mut object_content = vec![];
+        decoder
+            .take(object_size + 1)
+            .read_to_end(&mut object_content)
+            .map_err(|error| corrupt(&error.to_string()))?;
+
+        if object_content.len() as u64 != object_size {
+            return Err(corrupt(&format!(
+                "header declares {} bytes, but it has {}",
+                object_size,
+                if object_content.len() as u64 > object_size {
+                    String::from("more")
+                } else {
+                    object_content.len().to_string()
+                }
+            )));
+        }
+
+        if self.checksum_objects() {
+            let mut hasher = Sha1::default();
+            hasher.update(&header);
+            hasher.update([0]);
+            hasher.update(&object_content);
+
+            let actual_sha = format!("{:x}", hasher.finalize());
+
+            if actual_sha != sha {
+                return Err(corrupt(&format!("content hashes to {}", actual_sha)));
             }
         }
+
+        Ok((
+            String::from_utf8_lossy(object_type).into_owned(),
+            object_content,
+        ))
+    }
+
+    // Returns object associated to a given hash, or its replacement if there is one.
+    pub fn object_read(&self, sha: &str) -> Result<GitObject, String> {
+        let (object_type, object_content) = self.object_read_raw(sha)?;
+        let content = String::from_utf8_lossy(&object_content).into_owned();
+
+        match object_type.as_str() {
+            "commit" => Ok(GitObject::Commit(content)),
+            "tree" => Ok(GitObject::Tree(content)),
+            "tag" => Ok(GitObject::Tag(content)),
+            "blob" => Ok(GitObject::Blob(content)),
+            _ => Err(String::from("Object type does not match any known types.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{object::blob::Blob, testing::TestRepository};
+
+    fn check(config: &str) -> Result<(), String> {
+        let mut ini = Ini::new();
+        ini.read(config.to_owned()).unwrap();
+
+        check_repository_format(&ini)
+    }
+
+    fn with_extension(version: u32, extension: &str, value: &str) -> Result<(), String> {
+        check(&format!(
+            "[core]\nrepositoryformatversion = {}\n[extensions]\n{} = {}\n",
+            version, extension, value
+        ))
+    }
+
+    #[test]
+    fn format_versions() {
+        assert!(check("").is_ok());
+        assert!(check("[core]\nrepositoryformatversion = 0\n").is_ok());
+        assert!(check("[core]\nrepositoryformatversion = 1\n").is_ok());
+        assert!(check("[core]\nrepositoryformatversion = 2\n").is_err());
+        assert!(check("[core]\nrepositoryformatversion = one\n").is_err());
+    }
+
+    #[test]
+    fn extensions_valid_in_both_versions() {
+        for version in [0, 1] {
+            assert!(with_extension(version, "noop", "true").is_ok());
+            assert!(with_extension(version, "worktreeConfig", "true").is_ok());
+            assert!(with_extension(version, "preciousObjects", "true").is_ok());
+            assert!(with_extension(version, "partialClone", "origin").is_ok());
+        }
+    }
+
+    #[test]
+    fn extensions_needing_version_1() {
+        assert!(with_extension(1, "objectFormat", "sha1").is_ok());
+        assert!(with_extension(1, "objectFormat", "sha256").is_err());
+        assert!(with_extension(1, "refStorage", "files").is_ok());
+        assert!(with_extension(1, "refStorage", "reftable").is_err());
+        assert!(with_extension(0, "objectFormat", "sha1").is_err());
+        assert!(with_extension(0, "refStorage", "files").is_err());
+    }
+
+    #[test]
+    fn unknown_extensions() {
+        assert!(with_extension(0, "somethingNew", "true").is_ok());
+        assert!(with_extension(1, "somethingNew", "true").is_err());
+    }
+
+    #[test]
+    fn sweeps_old_temporary_objects() {
+        let test = TestRepository::new("sweeps_old_temporary_objects");
+        let objects = test.repo.repo_path("objects");
+        let blob = test.repo.object_write(&Blob::new(b"kept\n".to_vec()), true);
+
+        let temporary = |directory: &str, name: &str, age: u64| {
+            create_dir_all(objects.join(directory)).unwrap();
+            let path = objects.join(directory).join(name);
+            let modified = SystemTime::now() - Duration::from_secs(age);
+
+            File::create(&path).unwrap().set_modified(modified).unwrap();
+
+            path
+        };
+
+        let old = temporary("ab", "tmp_obj_1_0", 7200);
+        let new = temporary("cd", "tmp_obj_1_1", 0);
+        let old_next_to_object = temporary(&blob[..2], "tmp_obj_1_2", 7200);
+        let not_temporary = temporary("ef", "other", 7200);
+
+        let removed = test
+            .repo
+            .object_sweep_temporaries(Duration::from_secs(3600))
+            .unwrap();
+
+        assert_eq!(removed, 2);
+        assert!(!old.exists() && !old_next_to_object.exists());
+        assert!(new.exists() && not_temporary.exists());
+        assert!(test.repo.object_exists(&blob));
+        // Fan-out directories left empty are removed too.
+        assert!(!objects.join("ab").exists());
+
+        assert_eq!(
+            test.repo.object_sweep_temporaries(Duration::ZERO).unwrap(),
+            1
+        );
+        assert!(!new.exists());
     }
 }
@@ -0,0 +1,92 @@
+use std::{
+    fs::{create_dir_all, read_dir, write},
+    io,
+    path::Path,
+};
+
+use super::{
+    git_object::kvlm_parser,
+    platform::rename_replace,
+    repository::{Repository, MAX_PEEL_DEPTH},
+};
+
+// Auxiliary files that let dumb transports (a plain static web server) clone a
+// repository, as written by `git update-server-info`:
+//
+// - info/refs lists every ref as `<sha>\t<refname>`, plus a
+//   `<sha>\t<refname>^{}` line with the peeled object of annotated tags.
+// - objects/info/packs lists the available packs as `P <pack name>`.
+
+// Returns the object an annotated tag ultimately points to, or None if the
+// object is not a tag or the tags loop (which replacements make possible).
+fn peel_tag(repo: &Repository, sha: &str) -> Option<String> {
+    let mut current = sha.to_owned();
+
+    for depth in 0..=MAX_PEEL_DEPTH {
+        match repo.object_read_raw(&current) {
+            Ok((object_type, content)) if object_type == "tag" => {
+                let content = String::from_utf8_lossy(&content);
+                let kvlm = kvlm_parser(&content).ok()?;
+                let (_, object) = kvlm.iter().find(|(key, _)| *key == "object")?;
+
+                current = object.to_owned();
+            }
+            _ => return (depth > 0).then_some(current),
+        }
+    }
+
+    None
+}
+
+fn info_refs(repo: &Repository) -> String {
+    let mut content = String::new();
+
+    for refname in repo.ref_list("refs") {
+        let sha = match repo.ref_read(&refname) {
+            Some(sha) => sha,
+            None => continue,
+        };
+
+        content.push_str(&format!("{}\t{}\n", sha, refname));
+
+        if let Some(peeled) = peel_tag(repo, &sha) {
+            content.push_str(&format!("{}\t{}^{{}}\n", peeled, refname));
+        }
+    }
+
+    content
+}
+
+fn info_packs(repo: &Repository) -> String {
+    let mut packs = match read_dir(repo.repo_path("objects/pack")) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("pack-") && name.ends_with(".pack"))
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    packs.sort();
+
+    let mut content: String = packs.iter().map(|pack| format!("P {}\n", pack)).collect();
+    content.push('\n');
+
+    content
+}
+
+// Writes the file through a temporary one, so a web server never serves a
+// partially written file.
+fn write_info_file(path: &Path, content: &str) -> io::Result<()> {
+    create_dir_all(path.parent().unwrap())?;
+
+    let tmp_path = path.with_extension("tmp");
+    write(&tmp_path, content)?;
+
+    rename_replace(&tmp_path, path)
+}
+
+pub fn update_server_info(repo: &Repository) -> io::Result<()> {
+    write_info_file(&repo.repo_path("info/refs"), &info_refs(repo))?;
+    write_info_file(&repo.repo_path("objects/info/packs"), &info_packs(repo))
+}
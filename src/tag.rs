@@ -0,0 +1,278 @@
+use std::{
+    env,
+    fs::{remove_file, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    process::{self, Command, Stdio},
+    thread,
+};
+
+use super::{
+    for_each_ref::{list_ref_infos, RefInfo},
+    git_object::kvlm_parser,
+    grafts::Grafts,
+    pathspec::Pathspec,
+    repository::Repository,
+};
+
+// Listing and verifying tags, as done by `got tag`.
+
+const PGP_SIGNATURE_START: &str = "-----BEGIN PGP SIGNATURE-----";
+
+// Returns the tags whose short names (`v1.0`) match the patterns, and which
+// contain the given commit if any: the commit is the tagged one or one of its
+// ancestors.
+pub fn list_tags(
+    repo: &Repository,
+    patterns: &Pathspec,
+    contains: Option<&str>,
+) -> Result<Vec<RefInfo>, String> {
+    let contains = match contains {
+        Some(commit) => Some(repo.object_find(commit, Some("commit"))?),
+        None => None,
+    };
+
+    let grafts = Grafts::load(repo);
+    let tags = Pathspec::parse(&[String::from("refs/tags")])?;
+
+    Ok(list_ref_infos(repo, &tags)?
+        .into_iter()
+        .filter(|info| patterns.matches(short_name(info)))
+        .filter(|info| match &contains {
+            // Tags of trees and blobs contain no commits.
+            Some(commit) => repo
+                .object_find(&info.objectname, Some("commit"))
+                .map(|tagged| grafts.is_ancestor(repo, commit, &tagged))
+                .unwrap_or(false),
+            None => true,
+        })
+        .collect())
+}
+
+pub fn short_name(info: &RefInfo) -> &str {
+    info.refname.trim_start_matches("refs/tags/")
+}
+
+// Returns the message of an annotated tag without its signature, or the
+// message of the commit a lightweight tag points to.
+pub fn annotation(repo: &Repository, info: &RefInfo) -> String {
+    let content = match repo.object_read_raw(&info.objectname) {
+        Ok((object_type, content)) if object_type == "tag" || object_type == "commit" => content,
+        _ => return String::new(),
+    };
+
+    let content = String::from_utf8_lossy(&content);
+
+    let message = kvlm_parser(&content)
+        .ok()
+        .and_then(|kvlm| kvlm.into_iter().find(|(key, _)| key.is_empty()))
+        .map(|(_, message)| message)
+        .unwrap_or_default();
+
+    match message.find(PGP_SIGNATURE_START) {
+        Some(index) => message[..index].to_owned(),
+        None => message,
+    }
+}
+
+// Creates a file for the signature in the shared temporary directory. Names
+// are predictable, so the file is created only if nothing (like a symbolic link
+// planted by another user) is there yet, trying other names otherwise.
+fn create_signature_file(sha: &str) -> io::Result<(PathBuf, File)> {
+    let mut attempt = 0;
+
+    loop {
+        let path = env::temp_dir().join(format!(
+            "got_tag_signature_{}_{}_{}",
+            process::id(),
+            sha,
+            attempt
+        ));
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Checks the signature of an annotated tag with gpg (or gpg.program), as git
+// does. Returns the signed part of the tag and what gpg had to say about it,
+// or an error including gpg's output if the signature is not good. The tag is
+// handed to gpg byte for byte, as any change would break the signature.
+pub fn verify_tag(repo: &Repository, name: &str) -> Result<(Vec<u8>, String), String> {
+    let refname = format!("refs/tags/{}", name);
+    let sha = repo
+        .ref_read(&refname)
+        .ok_or_else(|| format!("tag '{}' not found.", name))?;
+
+    let (object_type, content) = repo.object_read_raw(&sha)?;
+
+    if object_type != "tag" {
+        return Err(format!(
+            "{}: cannot verify a non-tag object of type {}.",
+            name, object_type
+        ));
+    }
+
+    // The signature goes at the end of the message, and signs everything
+    // before it.
+    let start = PGP_SIGNATURE_START.as_bytes();
+    let (payload, signature) = match content
+        .windows(start.len())
+        .rposition(|bytes| bytes == start)
+    {
+        Some(index) => content.split_at(index),
+        None => return Err(format!("{}: no signature found", name)),
+    };
+
+    let (signature_path, mut signature_file) = create_signature_file(&sha)
+        .map_err(|error| format!("Could not write signature to verify: {}", error))?;
+    let written = signature_file.write_all(signature);
+    drop(signature_file);
+
+    if let Err(error) = written {
+        let _ = remove_file(&signature_path);
+        return Err(format!("Could not write signature to verify: {}", error));
+    }
+
+    let program = repo
+        .config_get("gpg", "program")
+        .unwrap_or_else(|| String::from("gpg"));
+
+    let output = Command::new(&program)
+        .args(["--status-fd=1", "--keyid-format=long", "--verify"])
+        .arg(&signature_path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            let mut stdin = child.stdin.take().unwrap();
+
+            // The payload is written from another thread while the output is
+            // read, as a program writing a lot before reading its input would
+            // otherwise wait on us forever. The program is always waited for,
+            // and one exiting without reading everything fails on its own.
+            thread::scope(|scope| {
+                let writer = scope.spawn(move || stdin.write_all(payload));
+                let output = child.wait_with_output();
+                let written = writer
+                    .join()
+                    .unwrap_or_else(|_| Err(io::Error::other("writing the tag panicked")));
+
+                match written {
+                    Err(error) if error.kind() != io::ErrorKind::BrokenPipe => Err(error),
+                    _ => output,
+                }
+            })
+        });
+
+    let _ = remove_file(&signature_path);
+
+    let output = output.map_err(|error| format!("Could not run {}: {}", program, error))?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let messages = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    // Like git, the signature is good only if gpg says so in its status
+    // output, not just through its exit code.
+    let good = output.status.success()
+        && status
+            .lines()
+            .any(|line| line.starts_with("[GNUPG:] GOODSIG "))
+        && !status
+            .lines()
+            .any(|line| line.starts_with("[GNUPG:] BADSIG "));
+
+    if good {
+        Ok((payload.to_vec(), messages))
+    } else {
+        Err(format!(
+            "could not verify the tag '{}'\n{}",
+            name,
+            messages.trim_end()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::*;
+    use crate::{object::tag::Tag, platform::set_executable, testing::TestRepository};
+
+    const SIGNATURE: &str =
+        "-----BEGIN PGP SIGNATURE-----\n\nc2lnbmF0dXJl\n-----END PGP SIGNATURE-----\n";
+
+    // A repository with a signed tag named big, with a message larger than a
+    // pipe can hold, and a gpg.program running the given shell script.
+    fn signed_tag(name: &str, script: &str) -> TestRepository {
+        let mut test = TestRepository::new(name);
+        let tag = test.repo.object_write(
+            &Tag::new(format!(
+                "object {}\ntype commit\ntag big\ntagger A U Thor <author@example.com> 1666772992 +0100\n\n{}\n{}",
+                "1".repeat(40),
+                "message ".repeat(100_000),
+                SIGNATURE
+            )),
+            true,
+        );
+        test.repo.ref_write("refs/tags/big", &tag).unwrap();
+
+        test.write_file("gpg", &format!("#!/bin/sh\n{}", script));
+        let program = test.repo.worktree().join("gpg");
+        set_executable(&program, true).unwrap();
+        test.config_set("gpg", "program", program.to_str().unwrap());
+
+        test
+    }
+
+    // Verifies tag big, failing if it takes too long (like when deadlocked).
+    // The repository is opened again in a thread of its own, which is left
+    // behind if it never finishes.
+    fn verify(test: &TestRepository) -> Result<(Vec<u8>, String), String> {
+        let (sender, receiver) = mpsc::channel();
+        let path = test.repo.worktree().clone();
+
+        thread::spawn(move || {
+            let repo = Repository::open(path).unwrap();
+            let _ = sender.send(verify_tag(&repo, "big"));
+        });
+
+        receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("verifying the tag did not finish")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn program_writing_before_reading_does_not_deadlock() {
+        let test = signed_tag(
+            "program_writing_before_reading",
+            "head -c 1000000 /dev/zero\necho\ncat > /dev/null\necho '[GNUPG:] GOODSIG 0 A U Thor'\necho 'Good signature' >&2\n",
+        );
+
+        let (payload, messages) = verify(&test).unwrap();
+
+        assert!(payload.ends_with(b"message \n"));
+        assert_eq!(messages, "Good signature\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn program_exiting_without_reading_fails_verification() {
+        let test = signed_tag(
+            "program_exiting_without_reading",
+            "echo 'no key' >&2\nexit 2\n",
+        );
+
+        let error = verify(&test).unwrap_err();
+
+        assert_eq!(error, "could not verify the tag 'big'\nno key");
+    }
+}
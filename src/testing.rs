@@ -0,0 +1,103 @@
+use std::{
+    env,
+    fs::{create_dir_all, read, remove_dir_all, write},
+    process,
+};
+
+use configparser::ini::Ini;
+
+use super::{
+    object::{blob::Blob, commit::Commit, oid::Oid, tree::FileMode, tree_builder::TreeBuilder},
+    repository::Repository,
+};
+
+// A repository in the temporary directory for tests that need one on disk,
+// removed when dropped.
+
+const SIGNATURE: &str = "A U Thor <author@example.com> 1666772992 +0100";
+
+pub(crate) struct TestRepository {
+    pub repo: Repository,
+}
+
+impl TestRepository {
+    // Creates an empty repository. Names must be unique among tests, as they
+    // run in parallel.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("got_test_{}_{}", process::id(), name));
+        let _ = remove_dir_all(&path);
+
+        TestRepository {
+            repo: Repository::create(path).unwrap(),
+        }
+    }
+
+    // Writes a blob, returning its id.
+    pub fn blob(&self, content: &str) -> Oid {
+        let sha = self
+            .repo
+            .object_write(&Blob::new(content.as_bytes().to_vec()), true);
+
+        Oid::from_hex(&sha).unwrap()
+    }
+
+    // Writes a tree with the given files, mode and contents.
+    pub fn tree(&self, files: &[(&str, FileMode, &str)]) -> String {
+        let mut builder = TreeBuilder::new();
+
+        for (path, mode, content) in files {
+            builder.insert(path, *mode, self.blob(content)).unwrap();
+        }
+
+        builder.write(&self.repo).to_hex()
+    }
+
+    // Writes a commit of a tree with the given files.
+    pub fn commit(&self, files: &[(&str, FileMode, &str)], parents: &[&str]) -> String {
+        let mut content = format!("tree {}\n", self.tree(files));
+
+        for parent in parents {
+            content.push_str(&format!("parent {}\n", parent));
+        }
+
+        content.push_str(&format!(
+            "author {}\ncommitter {}\n\nmessage\n",
+            SIGNATURE, SIGNATURE
+        ));
+
+        self.repo.object_write(&Commit::new(content), true)
+    }
+
+    // Sets a configuration value, reopening the repository to read it.
+    pub fn config_set(&mut self, section: &str, key: &str, value: &str) {
+        let path = self.repo.repo_path("config");
+        let mut config = Ini::new();
+
+        config.load(&path).unwrap();
+        config.set(section, key, Some(value.to_owned()));
+        config.write(&path).unwrap();
+
+        self.repo = Repository::open(self.repo.worktree().clone()).unwrap();
+    }
+
+    // Writes a file in the worktree, creating its leading directories.
+    pub fn write_file(&self, path: &str, content: &str) {
+        let path = self.repo.worktree().join(path);
+
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, content).unwrap();
+    }
+
+    // Returns the contents of a worktree file, or None if it cannot be read.
+    pub fn read_file(&self, path: &str) -> Option<String> {
+        read(self.repo.worktree().join(path))
+            .ok()
+            .map(|content| String::from_utf8(content).unwrap())
+    }
+}
+
+impl Drop for TestRepository {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(self.repo.worktree());
+    }
+}
@@ -0,0 +1,651 @@
+use std::{env, fs::read, path::Path, sync::OnceLock};
+
+use super::date::{civil_from_days, days_from_civil};
+
+// Offset of the local timezone at a given time, which is what signatures
+// record. There is no portable way to ask the system, so it is worked out like
+// the C library does: from $TZ if set, otherwise from /etc/localtime.
+//
+// $TZ may name a file of the timezone database (`Europe/Madrid`,
+// `:Europe/Madrid`, or an absolute path) or be a POSIX rule like
+// `CET-1CEST,M3.5.0,M10.5.0/3`. Anything unreadable is taken as UTC. Like in
+// the C library, the timezone is read once, and later changes to $TZ have no
+// effect.
+
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const LOCALTIME_PATH: &str = "/etc/localtime";
+
+// A POSIX TZ rule. Offsets are in seconds east of UTC, unlike in the rule
+// text, where they are west of it.
+pub(crate) struct Rule {
+    std_offset: i64,
+    dst: Option<DstRule>,
+}
+
+struct DstRule {
+    offset: i64,
+    start: Transition,
+    end: Transition,
+}
+
+// When a year's change to or from daylight saving time happens, as a day and
+// the seconds after midnight of that day (which may be negative or over 24h).
+struct Transition {
+    day: TransitionDay,
+    time: i64,
+}
+
+enum TransitionDay {
+    // `Jn`: day 1 to 365, never counting February 29th.
+    Julian(i64),
+    // `n`: day 0 to 365, counting February 29th.
+    ZeroBased(i64),
+    // `Mm.w.d`: day d (0 is Sunday) of week w (5 is the last) of month m.
+    MonthWeekDay(i64, i64, i64),
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+// Returns the days since the unix epoch of a transition day in a given year.
+fn transition_day(day: &TransitionDay, year: i64) -> i64 {
+    let january_first = days_from_civil(year, 1, 1);
+
+    match *day {
+        TransitionDay::Julian(day) => {
+            january_first + day - 1
+                + if is_leap_year(year) && day >= 60 {
+                    1
+                } else {
+                    0
+                }
+        }
+        TransitionDay::ZeroBased(day) => january_first + day,
+        TransitionDay::MonthWeekDay(month, week, weekday) => {
+            let month_first = days_from_civil(year, month, 1);
+            let next_month_first = if month == 12 {
+                days_from_civil(year + 1, 1, 1)
+            } else {
+                days_from_civil(year, month + 1, 1)
+            };
+
+            // The unix epoch was a Thursday.
+            let first_weekday = (month_first + 4).rem_euclid(7);
+            let mut day = month_first + (weekday - first_weekday).rem_euclid(7) + (week - 1) * 7;
+
+            while day >= next_month_first {
+                day -= 7;
+            }
+
+            day
+        }
+    }
+}
+
+// Parses the `[+-]hh[:mm[:ss]]` of a rule into seconds, with the sign of the
+// text (positive west of UTC).
+fn parse_rule_time(rule: &mut &str) -> Option<i64> {
+    let sign = match rule.as_bytes().first() {
+        Some(b'-') => -1,
+        Some(b'+') => 1,
+        _ => 0,
+    };
+
+    if sign != 0 {
+        *rule = &rule[1..];
+    }
+
+    let end = rule
+        .find(|c: char| !c.is_ascii_digit() && c != ':')
+        .unwrap_or(rule.len());
+    let (time, rest) = rule.split_at(end);
+    *rule = rest;
+
+    let fields = time.split(':').collect::<Vec<_>>();
+
+    if fields.len() > 3 {
+        return None;
+    }
+
+    let mut seconds = 0;
+
+    for (field, multiplier) in fields.iter().zip([3600, 60, 1]) {
+        if field.is_empty() || field.len() > 3 {
+            return None;
+        }
+
+        seconds += field.parse::<i64>().ok()? * multiplier;
+    }
+
+    Some(if sign < 0 { -seconds } else { seconds })
+}
+
+// Skips a timezone abbreviation, either alphabetic (`CET`) or quoted (`<+03>`).
+fn skip_rule_name(rule: &mut &str) -> Option<()> {
+    let length = if let Some(quoted) = rule.strip_prefix('<') {
+        quoted.find('>')? + 2
+    } else {
+        rule.find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rule.len())
+    };
+
+    if length < 3 {
+        return None;
+    }
+
+    *rule = &rule[length..];
+
+    Some(())
+}
+
+fn parse_transition(rule: &mut &str) -> Option<Transition> {
+    let day = if let Some(rest) = rule.strip_prefix('M') {
+        let end = rest.find([',', '/']).unwrap_or(rest.len());
+        let fields = rest[..end]
+            .split('.')
+            .map(|field| field.parse::<i64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        *rule = &rest[end..];
+
+        match fields[..] {
+            [month, week, weekday]
+                if (1..=12).contains(&month)
+                    && (1..=5).contains(&week)
+                    && (0..=6).contains(&weekday) =>
+            {
+                TransitionDay::MonthWeekDay(month, week, weekday)
+            }
+            _ => return None,
+        }
+    } else {
+        let julian = rule.starts_with('J');
+        let rest = rule.strip_prefix('J').unwrap_or(rule);
+        let end = rest.find([',', '/']).unwrap_or(rest.len());
+        let day = rest[..end].parse::<i64>().ok()?;
+        *rule = &rest[end..];
+
+        match julian {
+            true if (1..=365).contains(&day) => TransitionDay::Julian(day),
+            false if (0..=365).contains(&day) => TransitionDay::ZeroBased(day),
+            _ => return None,
+        }
+    };
+
+    let time = match rule.strip_prefix('/') {
+        Some(rest) => {
+            *rule = rest;
+            parse_rule_time(rule)?
+        }
+        None => 2 * 3600,
+    };
+
+    Some(Transition { day, time })
+}
+
+fn parse_rule(mut rule: &str) -> Option<Rule> {
+    skip_rule_name(&mut rule)?;
+    let std_offset = -parse_rule_time(&mut rule)?;
+
+    if rule.is_empty() {
+        return Some(Rule {
+            std_offset,
+            dst: None,
+        });
+    }
+
+    skip_rule_name(&mut rule)?;
+
+    let dst_offset = if rule.is_empty() || rule.starts_with(',') {
+        std_offset + 3600
+    } else {
+        -parse_rule_time(&mut rule)?
+    };
+
+    // Without transition dates, the C library defaults to the US rules.
+    let mut transitions = if rule.is_empty() {
+        ",M3.2.0,M11.1.0"
+    } else {
+        rule
+    };
+
+    transitions = transitions.strip_prefix(',')?;
+    let start = parse_transition(&mut transitions)?;
+    transitions = transitions.strip_prefix(',')?;
+    let end = parse_transition(&mut transitions)?;
+
+    if !transitions.is_empty() {
+        return None;
+    }
+
+    Some(Rule {
+        std_offset,
+        dst: Some(DstRule {
+            offset: dst_offset,
+            start,
+            end,
+        }),
+    })
+}
+
+impl Rule {
+    fn offset_at(&self, timestamp: i64) -> i64 {
+        let dst = match &self.dst {
+            Some(dst) => dst,
+            None => return self.std_offset,
+        };
+
+        let (year, _, _) = civil_from_days((timestamp + self.std_offset).div_euclid(86400));
+
+        // The start is given in standard time and the end in daylight saving
+        // time.
+        let start = transition_day(&dst.start.day, year) * 86400 + dst.start.time - self.std_offset;
+        let end = transition_day(&dst.end.day, year) * 86400 + dst.end.time - dst.offset;
+
+        let in_dst = if start < end {
+            start <= timestamp && timestamp < end
+        } else {
+            // Southern hemisphere, where daylight saving time spans the new
+            // year.
+            !(end <= timestamp && timestamp < start)
+        };
+
+        if in_dst {
+            dst.offset
+        } else {
+            self.std_offset
+        }
+    }
+}
+
+// A parsed tzfile(5): the instants the offset changes, the offset each change
+// switches to, and the rule for the times after the last one.
+pub(crate) struct TimezoneFile {
+    transitions: Vec<(i64, i64)>,
+    default_offset: i64,
+    footer: Option<Rule>,
+}
+
+fn read_be(bytes: &[u8], start: usize, length: usize) -> Option<i64> {
+    let bytes = bytes.get(start..start + length)?;
+    let mut value = 0u64;
+
+    for byte in bytes {
+        value = (value << 8) | *byte as u64;
+    }
+
+    // Sign extend values narrower than 8 bytes.
+    let shift = 64 - 8 * length as u32;
+    Some(((value << shift) as i64) >> shift)
+}
+
+fn parse_timezone_file(content: &[u8]) -> Option<TimezoneFile> {
+    if content.get(..4)? != b"TZif" {
+        return None;
+    }
+
+    let version = *content.get(4)?;
+
+    let counts = |header: usize| -> Option<[usize; 6]> {
+        let mut counts = [0; 6];
+
+        for (index, count) in counts.iter_mut().enumerate() {
+            *count = read_be(content, header + 20 + index * 4, 4)? as u32 as usize;
+        }
+
+        Some(counts)
+    };
+
+    // Version 1 data uses 4 byte times. Later versions repeat it with 8 byte
+    // times after it, followed by a POSIX rule for times after the last
+    // transition.
+    let [ut_count, std_count, leap_count, time_count, type_count, char_count] = counts(0)?;
+    let v1_length =
+        time_count * 5 + type_count * 6 + char_count + leap_count * 8 + std_count + ut_count;
+
+    let (header, time_size) = if version >= b'2' {
+        (44 + v1_length, 8)
+    } else {
+        (0, 4)
+    };
+
+    let [ut_count, std_count, leap_count, time_count, type_count, char_count] = counts(header)?;
+
+    if type_count == 0 {
+        return None;
+    }
+
+    let times_start = header + 44;
+    let indices_start = times_start + time_count * time_size;
+    let types_start = indices_start + time_count;
+
+    let type_offset = |index: usize| -> Option<i64> {
+        if index >= type_count {
+            return None;
+        }
+
+        read_be(content, types_start + index * 6, 4)
+    };
+
+    let mut transitions = vec![];
+
+    for index in 0..time_count {
+        let time = read_be(content, times_start + index * time_size, time_size)?;
+        let type_index = *content.get(indices_start + index)? as usize;
+
+        transitions.push((time, type_offset(type_index)?));
+    }
+
+    // Times before the first transition use the first standard time type, or
+    // the first type if all are daylight saving time.
+    let default_offset = (0..type_count)
+        .find(|index| content.get(types_start + index * 6 + 4) == Some(&0))
+        .map_or(type_offset(0), type_offset)?;
+
+    let data_end = types_start
+        + type_count * 6
+        + char_count
+        + leap_count * (time_size + 4)
+        + std_count
+        + ut_count;
+
+    let footer = if version >= b'2' {
+        content
+            .get(data_end..)
+            .and_then(|footer| footer.strip_prefix(b"\n"))
+            .and_then(|footer| std::str::from_utf8(footer).ok())
+            .and_then(|footer| footer.split('\n').next())
+            .filter(|footer| !footer.is_empty())
+            .and_then(parse_rule)
+    } else {
+        None
+    };
+
+    Some(TimezoneFile {
+        transitions,
+        default_offset,
+        footer,
+    })
+}
+
+impl TimezoneFile {
+    fn offset_at(&self, timestamp: i64) -> i64 {
+        let index = self
+            .transitions
+            .partition_point(|(time, _)| *time <= timestamp);
+
+        match (index, &self.footer) {
+            (0, _) => self.default_offset,
+            (index, Some(footer)) if index == self.transitions.len() => footer.offset_at(timestamp),
+            (index, _) => self.transitions[index - 1].1,
+        }
+    }
+}
+
+// A timezone, as given by a rule or by a file of the timezone database.
+pub(crate) enum Timezone {
+    Rule(Rule),
+    File(TimezoneFile),
+}
+
+fn read_timezone_file(path: &Path) -> Option<Timezone> {
+    Some(Timezone::File(parse_timezone_file(&read(path).ok()?)?))
+}
+
+fn load_local_timezone() -> Option<Timezone> {
+    let tz = match env::var("TZ") {
+        Ok(tz) => tz,
+        Err(_) => return read_timezone_file(Path::new(LOCALTIME_PATH)),
+    };
+
+    let name = tz.strip_prefix(':').unwrap_or(&tz);
+
+    if name.is_empty() {
+        return None;
+    }
+
+    // Names are looked up in the database first, as some of them (`EST5EDT`)
+    // are valid rules too, and names going up the tree are not allowed.
+    let path = if name.starts_with('/') {
+        Some(Path::new(name).to_path_buf())
+    } else if !name.split('/').any(|component| component == "..") {
+        Some(Path::new(ZONEINFO_DIR).join(name))
+    } else {
+        None
+    };
+
+    path.and_then(|path| read_timezone_file(&path))
+        .or_else(|| Timezone::parse_rule(name))
+}
+
+impl Timezone {
+    fn utc() -> Self {
+        Timezone::Rule(Rule {
+            std_offset: 0,
+            dst: None,
+        })
+    }
+
+    pub(crate) fn parse_rule(rule: &str) -> Option<Self> {
+        Some(Timezone::Rule(parse_rule(rule)?))
+    }
+
+    // The timezone of the process, read the first time it is needed and kept
+    // for the rest of it, as the C library does.
+    pub(crate) fn local() -> &'static Timezone {
+        static LOCAL: OnceLock<Timezone> = OnceLock::new();
+
+        LOCAL.get_or_init(|| load_local_timezone().unwrap_or_else(Timezone::utc))
+    }
+
+    // Returns the offset from UTC, in minutes, at a unix timestamp.
+    pub(crate) fn offset_minutes(&self, timestamp: i64) -> i32 {
+        let seconds = match self {
+            Timezone::Rule(rule) => rule.offset_at(timestamp),
+            Timezone::File(file) => file.offset_at(timestamp),
+        };
+
+        (seconds / 60) as i32
+    }
+}
+
+// Returns the offset from UTC, in minutes, of the local timezone at a unix
+// timestamp.
+pub fn local_offset_minutes(timestamp: i64) -> i32 {
+    Timezone::local().offset_minutes(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+
+    fn rule(text: &str) -> Rule {
+        parse_rule(text).unwrap_or_else(|| panic!("{} should parse", text))
+    }
+
+    // Builds a tzfile(5) with the given transitions (time and type index) and
+    // types (offset and whether it is daylight saving time). Version 2 files
+    // repeat the data with 8 byte times and end with a POSIX rule.
+    fn tzif(
+        version: u8,
+        transitions: &[(i64, u8)],
+        types: &[(i64, bool)],
+        footer: &str,
+    ) -> Vec<u8> {
+        let block = |time_size: usize| {
+            let mut block = b"TZif".to_vec();
+            block.push(version);
+            block.extend([0; 15]);
+
+            for count in [0, 0, 0, transitions.len(), types.len(), 0] {
+                block.extend((count as u32).to_be_bytes());
+            }
+
+            for (time, _) in transitions {
+                block.extend(&time.to_be_bytes()[8 - time_size..]);
+            }
+
+            block.extend(transitions.iter().map(|(_, index)| index));
+
+            for (offset, dst) in types {
+                block.extend((*offset as i32).to_be_bytes());
+                block.extend([*dst as u8, 0]);
+            }
+
+            block
+        };
+
+        let mut content = block(4);
+
+        if version >= b'2' {
+            content.extend(block(8));
+            content.extend(format!("\n{}\n", footer).as_bytes());
+        }
+
+        content
+    }
+
+    #[test]
+    fn parses_rules_without_daylight_saving_time() {
+        for (text, offset) in [
+            ("UTC0", 0),
+            ("JST-9", 9 * HOUR),
+            ("<+0330>-3:30", 3 * HOUR + 1800),
+            ("<-03>3", -3 * HOUR),
+            ("XXX+12:45:30", -(12 * HOUR + 45 * 60 + 30)),
+        ] {
+            assert_eq!(rule(text).offset_at(1666772992), offset, "{}", text);
+        }
+    }
+
+    #[test]
+    fn applies_northern_hemisphere_rules() {
+        let cet = rule("CET-1CEST,M3.5.0,M10.5.0/3");
+
+        // 2022-03-27 02:00 CET and 2022-10-30 03:00 CEST.
+        assert_eq!(cet.offset_at(1648342799), HOUR);
+        assert_eq!(cet.offset_at(1648342800), 2 * HOUR);
+        assert_eq!(cet.offset_at(1667091599), 2 * HOUR);
+        assert_eq!(cet.offset_at(1667091600), HOUR);
+        assert_eq!(cet.offset_at(1640995200), HOUR);
+        assert_eq!(cet.offset_at(1656633600), 2 * HOUR);
+    }
+
+    #[test]
+    fn applies_southern_hemisphere_rules() {
+        let sydney = rule("AEST-10AEDT,M10.1.0,M4.1.0/3");
+
+        // 2022-04-03 03:00 AEDT and 2022-10-02 02:00 AEST.
+        assert_eq!(sydney.offset_at(1648915199), 11 * HOUR);
+        assert_eq!(sydney.offset_at(1648915200), 10 * HOUR);
+        assert_eq!(sydney.offset_at(1664639999), 10 * HOUR);
+        assert_eq!(sydney.offset_at(1664640000), 11 * HOUR);
+        assert_eq!(sydney.offset_at(1640995200), 11 * HOUR);
+        assert_eq!(sydney.offset_at(1656633600), 10 * HOUR);
+    }
+
+    #[test]
+    fn applies_day_of_year_rules() {
+        // Daylight saving time from March 1st (day 60, or 59 counting from 0
+        // in a year that is not leap) to October 1st, both at 02:00.
+        for text in ["EST5EDT,J60,J274", "EST5EDT,59,273"] {
+            let rule = rule(text);
+
+            // 2022-03-01 06:59:59 and 07:00 UTC.
+            assert_eq!(rule.offset_at(1646117999), -5 * HOUR, "{}", text);
+            assert_eq!(rule.offset_at(1646118000), -4 * HOUR, "{}", text);
+        }
+
+        // Without dates, the US rules apply: 2022-03-13 02:00 EST.
+        let us = rule("EST5EDT");
+        assert_eq!(us.offset_at(1647154799), -5 * HOUR);
+        assert_eq!(us.offset_at(1647154800), -4 * HOUR);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for text in [
+            "",
+            "CET",
+            "CE-1",
+            "CET-1CEST,M13.1.0,M10.5.0",
+            "CET-1CEST,M3.6.0,M10.5.0",
+            "CET-1CEST,M3.5.7,M10.5.0",
+            "CET-1CEST,J0,J100",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M3.5.0,M10.5.0,",
+            "CET-1:2:3:4",
+        ] {
+            assert!(parse_rule(text).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_version_1_files() {
+        let content = tzif(
+            b'\0',
+            &[(1000, 1), (2000, 0)],
+            &[(HOUR, false), (2 * HOUR, true)],
+            "",
+        );
+        let file = parse_timezone_file(&content).unwrap();
+
+        assert_eq!(file.offset_at(0), HOUR);
+        assert_eq!(file.offset_at(999), HOUR);
+        assert_eq!(file.offset_at(1000), 2 * HOUR);
+        assert_eq!(file.offset_at(2000), HOUR);
+        assert_eq!(file.offset_at(1666772992), HOUR);
+    }
+
+    #[test]
+    fn uses_the_footer_of_version_2_files_after_the_last_transition() {
+        // Transitions up to 2021-10-31 01:00 UTC, then the CET rule.
+        let transitions = [(-2_000_000_000_000, 1), (1616893200, 1), (1635642000, 0)];
+        let types = [(HOUR, false), (2 * HOUR, true)];
+        let content = tzif(b'2', &transitions, &types, "CET-1CEST,M3.5.0,M10.5.0/3");
+        let file = parse_timezone_file(&content).unwrap();
+
+        // 8 byte times are read, so the first transition is not truncated.
+        assert_eq!(file.offset_at(-2_000_000_000_001), HOUR);
+        assert_eq!(file.offset_at(-1_000_000_000), 2 * HOUR);
+        assert_eq!(file.offset_at(1635641999), 2 * HOUR);
+        assert_eq!(file.offset_at(1635642000), HOUR);
+        assert_eq!(file.offset_at(1648342800), 2 * HOUR);
+        assert_eq!(file.offset_at(1667091600), HOUR);
+    }
+
+    #[test]
+    fn defaults_to_the_first_standard_time_type() {
+        let types = [(2 * HOUR, true), (HOUR, false)];
+        let content = tzif(b'2', &[(1000, 0)], &types, "");
+        let file = parse_timezone_file(&content).unwrap();
+
+        assert_eq!(file.offset_at(0), HOUR);
+        assert_eq!(file.offset_at(1000), 2 * HOUR);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let content = tzif(b'2', &[(1000, 1)], &[(HOUR, false), (2 * HOUR, true)], "");
+
+        for length in [0, 4, 43, 44, 60, content.len() / 2] {
+            assert!(
+                parse_timezone_file(&content[..length]).is_none(),
+                "{}",
+                length
+            );
+        }
+
+        // A transition to a type that does not exist.
+        assert!(parse_timezone_file(&tzif(b'2', &[(1000, 5)], &[(HOUR, false)], "")).is_none());
+
+        // No types at all.
+        assert!(parse_timezone_file(&tzif(b'2', &[], &[], "")).is_none());
+
+        let mut content = content;
+        content[..4].copy_from_slice(b"TZik");
+        assert!(parse_timezone_file(&content).is_none());
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Component, Path};
+
+use super::object::oid::Oid;
+
+// Validation of names that end up being joined into filesystem paths. Object
+// ids, ref names and tree entry paths can all come from untrusted sources
+// (command line, other repositories), so they must be checked before we touch
+// the filesystem with them.
+
+pub fn validate_object_id(sha: &str) -> Result<(), &'static str> {
+    Oid::from_hex(sha).map(|_| ())
+}
+
+// Checks a ref name against the rules of `git check-ref-format`. One-level
+// names (like HEAD or ORIG_HEAD) are allowed, as we use them for special refs.
+pub fn validate_ref_name(name: &str) -> Result<(), String> {
+    let invalid = |reason: &str| Err(format!("Invalid ref name {:?}: {}", name, reason));
+
+    if name.is_empty() {
+        return invalid("it is empty");
+    }
+
+    if name == "@" {
+        return invalid("it cannot be the single character '@'");
+    }
+
+    if name.starts_with('/') || name.ends_with('/') || name.contains("//") {
+        return invalid("it cannot begin or end with '/' or contain '//'");
+    }
+
+    if name.ends_with('.') {
+        return invalid("it cannot end with '.'");
+    }
+
+    if name.contains("..") {
+        return invalid("it cannot contain '..'");
+    }
+
+    if name.contains("@{") {
+        return invalid("it cannot contain '@{'");
+    }
+
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return invalid("it contains a forbidden character");
+    }
+
+    if name
+        .split('/')
+        .any(|component| component.starts_with('.') || component.ends_with(".lock"))
+    {
+        return invalid("no component can begin with '.' or end with '.lock'");
+    }
+
+    Ok(())
+}
+
+// Checks a slash separated path as stored in a tree. Components cannot be empty,
+// "." or "..", and cannot name the .got directory, otherwise writing the tree
+// out to the worktree could escape it or overwrite the repository itself.
+pub fn validate_tree_path(path: &str) -> Result<(), String> {
+    for component in path.split('/') {
+        if component.is_empty() || component == "." || component == ".." {
+            return Err(format!("Invalid path for a tree entry: {:?}", path));
+        }
+
+        if component.eq_ignore_ascii_case(".got") {
+            return Err(format!("Tree entry path cannot contain .got: {:?}", path));
+        }
+
+        if component.contains('\x00') || component.contains('\\') {
+            return Err(format!(
+                "Tree entry path contains a forbidden character: {:?}",
+                path
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Returns true if the relative path only goes downwards (no root, prefix or
+// ".." components), so joining it to a directory cannot escape it.
+pub fn is_contained_path(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}